@@ -4,45 +4,121 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+extern crate cc;
 extern crate rustkit_bindgen as gen;
 
 use std::env;
-use std::path::Path;
-use std::fs::File;
-use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::collections::HashSet;
 
-fn bind_system_header(sdk_root: &Path, header: &str, out_dir: &Path, top: &mut File) {
-    let mut header_path = sdk_root.to_owned();
-    header_path.push("usr/include");
-    header_path.push(header);
-    gen::bind_file(&sdk_root, &header_path, &out_dir);
-    write!(top, "include!(concat!(env!(\"OUT_DIR\"), \"/{}.rs\"));\n", header_path.file_stem().unwrap().to_str().unwrap()).unwrap();
+// The ObjC runtime defaults to Apple's libobjc, but can be swapped out
+// (e.g. for GNUstep's libobjc2) without touching the bindings.
+fn link_objc_runtime() {
+    println!("cargo:rerun-if-env-changed=RUSTKIT_OBJC_LIB");
+    println!("cargo:rerun-if-env-changed=RUSTKIT_OBJC_LIB_PATH");
+    let lib = env::var("RUSTKIT_OBJC_LIB").unwrap_or_else(|_| "objc".to_owned());
+    if let Ok(path) = env::var("RUSTKIT_OBJC_LIB_PATH") {
+        println!("cargo:rustc-link-search=native={}", path);
+    }
+    println!("cargo:rustc-link-lib={}", lib);
+}
+
+// ObjC exceptions can only be caught from ObjC, see objc::catch.
+fn build_exception_shim() {
+    println!("cargo:rerun-if-changed=src/exception.m");
+    cc::Build::new().
+        file("src/exception.m").
+        flag("-fobjc-exceptions").
+        compile("rustkit_exception");
+}
+
+// Frameworks newer than the oldest supported OS can be weak-linked so the
+// binary still loads without them. Link args only reach this crate's own
+// tests and examples, so anything depending on it has to pass
+// -weak_framework itself; see the README.
+fn weak_frameworks() -> HashSet<String> {
+    println!("cargo:rerun-if-env-changed=RUSTKIT_WEAK_FRAMEWORKS");
+    let frameworks: HashSet<String> =
+        env::var("RUSTKIT_WEAK_FRAMEWORKS").unwrap_or_default().
+        split(',').filter(|s| !s.is_empty()).map(|s| s.to_owned()).collect();
+    for f in &frameworks {
+        println!("cargo:rustc-link-arg=-Wl,-weak_framework,{}", f);
+    }
+    frameworks
+}
+
+// APIs introduced after the deployment target are left out, so using one
+// fails to compile instead of failing to load on older systems.
+fn deployment_target() -> Option<(u32, u32)> {
+    println!("cargo:rerun-if-env-changed=RUSTKIT_DEPLOYMENT_TARGET");
+    println!("cargo:rustc-check-cfg=cfg(rustkit_deployment_target)");
+    let target = env::var("RUSTKIT_DEPLOYMENT_TARGET").ok()?;
+    let mut parts = target.split('.').map(|p| p.parse::<u32>());
+    let version = match (parts.next(), parts.next()) {
+        (Some(Ok(major)), None) => (major, 0),
+        (Some(Ok(major)), Some(Ok(minor))) => (major, minor),
+        _ => panic!("RUSTKIT_DEPLOYMENT_TARGET should look like 10.15, not {:?}", target),
+    };
+    println!("cargo:rustc-cfg=rustkit_deployment_target");
+    Some(version)
+}
+
+// Binding is spread over a thread per core unless this says otherwise,
+// e.g. 1 to time it serially.
+fn bind_threads() -> usize {
+    println!("cargo:rerun-if-env-changed=RUSTKIT_BIND_THREADS");
+    env::var("RUSTKIT_BIND_THREADS").ok().map_or(0, |n| {
+        n.parse().unwrap_or_else(|_| panic!("RUSTKIT_BIND_THREADS should be a number, not {:?}", n))
+    })
+}
+
+// SDKROOT wins if it's set, as it is under xcrun and Xcode builds.
+fn sdk_root(target: Option<&str>) -> PathBuf {
+    println!("cargo:rerun-if-env-changed=SDKROOT");
+    match env::var("SDKROOT") {
+        Ok(root) => PathBuf::from(root),
+        Err(_) => gen::default_sdk_path(target),
+    }
 }
 
 fn main () {
+    link_objc_runtime();
+    build_exception_shim();
     let out_dir = env::var("OUT_DIR").unwrap();
     let out_dir = Path::new(&out_dir);
-    let sdk_root = Path::new("/Applications/Xcode.app/Contents/Developer/Platforms/MacOSX.platform/Developer/SDKs/MacOSX.sdk");
-    let frameworks = vec!["AVKit", "AppKit", "Foundation"];
-    let top_path = out_dir.join("top.rs");
-    let mut top = File::create(&top_path).unwrap();
-    bind_system_header(&sdk_root, "objc/NSObject.h", &out_dir, &mut top);
-    bind_system_header(&sdk_root, "MacTypes.h", &out_dir, &mut top);
-    bind_system_header(&sdk_root, "sys/acl.h", &out_dir, &mut top);
-    bind_system_header(&sdk_root, "hfs/hfs_unistr.h", &out_dir, &mut top);
-    bind_system_header(&sdk_root, "mach/message.h", &out_dir, &mut top);
-    bind_system_header(&sdk_root, "simd/types.h", &out_dir, &mut top);
-    let mut done: HashSet<String> = HashSet::new();
-    let mut deps: Vec<String> = frameworks.iter().map(|s| s.to_string()).collect();
-    while let Some(f) = deps.pop() {
-        let newdeps = gen::bind_framework(&sdk_root, &f, &out_dir);
-        write!(top, "pub mod {};\n", f).unwrap();
-        done.insert(f);
-        for d in &newdeps {
-            if !done.contains(d) && !deps.iter().any(|s| s == d) {
-                deps.push(d.clone());
-            }
-        }
+    let target = env::var("TARGET").ok();
+    let sdk_root = sdk_root(target.as_ref().map(|t| t.as_str()));
+    let config = gen::Config {
+        target: target.clone(),
+        weak_frameworks: weak_frameworks(),
+        deployment_target: deployment_target(),
+        ..Default::default()
+    };
+    let mut binder = gen::Binder::new(&sdk_root, &out_dir).
+        threads(bind_threads()).
+        header("objc/NSObject.h").
+        header("MacTypes.h").
+        header("sys/acl.h").
+        header("hfs/hfs_unistr.h").
+        header("mach/message.h").
+        header("simd/types.h").
+        framework("AVKit").
+        framework("Foundation");
+    // AppKit is macOS only (Mac Catalyst apps use UIKit instead).
+    let ios = target.map_or(false, |t| t.contains("-ios"));
+    if !ios {
+        binder = binder.framework("AppKit");
+    }
+    if env::var_os("CARGO_FEATURE_TEST_HEADERS").is_some() {
+        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        binder = binder.header(&format!("{}/tests/headers/RustKitTests.h", manifest_dir));
+        // The classes there need an implementation to link against.
+        println!("cargo:rerun-if-changed=tests/headers/RustKitTests.h");
+        println!("cargo:rerun-if-changed=tests/headers/RustKitTests.m");
+        cc::Build::new().
+            file("tests/headers/RustKitTests.m").
+            flag("-fobjc-arc").
+            compile("rustkit_tests");
     }
+    binder.generate(&config);
 }
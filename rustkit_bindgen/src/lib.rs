@@ -15,9 +15,13 @@ mod walker;
 
 use walker::{CursorKind, TypeKind};
 use std::path::{Path, PathBuf};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Instant;
 use quote::ToTokens;
 use proc_macro2::{Ident, Span};
 
@@ -36,18 +40,26 @@ fn cursor_dump(c: &walker::Cursor, p: Option<&str>) {
     });
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 enum Type {
     Void,
     Bool,
+    // ObjC's BOOL, which isn't always a C bool. See objc::BOOL.
+    ObjCBool,
     Int(bool, usize),
     Long(bool),
     Float(usize),
+    // f32 or f64 depending on the target's pointer width.
+    CGFloat,
+    Complex(usize),
     Pointer(Box<Type>, bool, bool),
     Record(String, bool),
     Enum(String),
     FunctionProto(Vec<Type>, Box<Type>, bool),
+    Block(Vec<Type>, Box<Type>, bool),
     FixedArray(Box<Type>, u64),
+    Vector(Box<Type>, u64),
+    IncompleteArray(Box<Type>, bool, bool),
     Typedef(String),
     InstanceType(bool),
     SelectorRef,
@@ -56,7 +68,33 @@ enum Type {
 }
 
 impl Type {
-    pub fn read(t: &walker::Ty, name: Option<String>, nonnull: bool) -> Type {
+    pub fn read(t: &walker::Ty, name: Option<String>, nonnull: bool, config: &Config) -> Type {
+        let nullability = if nonnull {
+            walker::Nullability::NonNull
+        } else {
+            walker::Nullability::Unspecified
+        };
+        Type::read_nullable(t, name, nullability, config)
+    }
+
+    // Array parameters decay to a pointer to their first element in C,
+    // so fixed size ones are taken by reference to keep their length.
+    pub fn read_param(t: &walker::Ty, config: &Config) -> Type {
+        match Type::read(t, None, false, config) {
+            arr @ Type::FixedArray(..) => {
+                let nonnull = t.nullability() != walker::Nullability::Nullable;
+                let is_const = t.canonical().element_ty().is_const();
+                Type::Pointer(Box::new(arr), nonnull, is_const)
+            },
+            ty => ty,
+        }
+    }
+
+    // Nullability written where a type is used overrides whatever the
+    // typedefs it goes through say, so only pick up an annotation if
+    // nothing further out has specified one yet.
+    fn read_nullable(t: &walker::Ty, name: Option<String>, nullability: walker::Nullability, config: &Config) -> Type {
+        let nonnull = nullability == walker::Nullability::NonNull;
         match t.kind() {
             TypeKind::Void => Type::Void,
             TypeKind::Bool => Type::Bool,
@@ -66,12 +104,37 @@ impl Type {
             TypeKind::UShort => Type::Int(false, 2),
             TypeKind::Int => Type::Int(true, 4),
             TypeKind::UInt => Type::Int(false, 4),
-            TypeKind::Long => Type::Long(true),
-            TypeKind::ULong => Type::Long(false),
+            // long is pointer sized on Apple's targets, which is what
+            // NSInteger relies on, but that isn't true everywhere.
+            TypeKind::Long | TypeKind::ULong => {
+                let signed = t.kind() == TypeKind::Long;
+                let size = t.size() as usize;
+                if size == config.pointer_width() {
+                    Type::Long(signed)
+                } else {
+                    Type::Int(signed, size)
+                }
+            },
             TypeKind::LongLong => Type::Int(true, 8),
             TypeKind::ULongLong => Type::Int(false, 8),
             TypeKind::Float => Type::Float(4),
             TypeKind::Double => Type::Float(8),
+            // long double is just a double on arm64.
+            TypeKind::LongDouble | TypeKind::Float128 => Type::Float(t.size() as usize),
+            TypeKind::Vector =>
+                Type::Vector(
+                    Box::new(Type::read(&t.component_ty(), None, false, config)),
+                    t.num_elements()),
+            TypeKind::Complex => {
+                match t.component_ty().kind() {
+                    TypeKind::Float => Type::Complex(4),
+                    TypeKind::Double => Type::Complex(8),
+                    k => {
+                        println!("Unhandled complex type {} with component kind {:?}", t.spelling(), k);
+                        Type::Void
+                    }
+                }
+            },
             TypeKind::Record => {
                 let decl = t.decl();
                 Type::Record(name.unwrap_or(decl.name()), decl.kind() == CursorKind::UnionDecl)
@@ -79,13 +142,15 @@ impl Type {
             TypeKind::Enum => Type::Enum(name.unwrap_or(t.decl().name())),
             TypeKind::ConstantArray =>
                 Type::FixedArray(
-                    Box::new(Type::read(&t.element_ty(), None, false)),
+                    Box::new(Type::read(&t.element_ty(), None, false, config)),
                     t.array_size()),
-            TypeKind::IncompleteArray =>
-                Type::Pointer(
-                    Box::new(Type::read(&t.element_ty(), None, false)),
+            TypeKind::IncompleteArray => {
+                let elem = t.element_ty();
+                Type::IncompleteArray(
+                    Box::new(Type::read(&elem, None, false, config)),
                     nonnull,
-                    false),
+                    elem.is_const())
+            },
             TypeKind::Typedef => {
                 let name = t.typedef_name();
                 match name.as_str() {
@@ -94,15 +159,26 @@ impl Type {
                             Box::new(Type::InstanceType(nonnull)),
                             nonnull,
                             false),
-                    "BOOL" => Type::Bool,
+                    "BOOL" => Type::ObjCBool,
+                    // These change width between 32 and 64-bit targets,
+                    // so they're kept pointer sized whatever the headers
+                    // were parsed for.
+                    "NSInteger" if !config.newtype_typedefs.contains(&name) => Type::Long(true),
+                    "NSUInteger" if !config.newtype_typedefs.contains(&name) => Type::Long(false),
+                    "CGFloat" if !config.newtype_typedefs.contains(&name) => Type::CGFloat,
                     _ => {
                         let inner =
-                            Type::read(
+                            Type::read_nullable(
                                 &t.decl().typedef_ty(),
                                 Some(name.clone()),
-                                nonnull
-                            );
-                        if inner.is_anonymous() {
+                                nullability,
+                                config);
+                        // Typedefs (usually from macros) of instancetype
+                        // must stay Self rather than become a named type.
+                        if inner.is_instancetype() {
+                            inner
+                        } else if inner.is_anonymous() ||
+                                  config.newtype_typedefs.contains(&name) {
                             Type::Typedef(name)
                         } else {
                             inner
@@ -111,23 +187,42 @@ impl Type {
                 }
             },
             TypeKind::Attributed => {
-                let n = t.nullability();
-                Type::read(&t.modified_ty(), name, n == walker::Nullability::NonNull)
+                let n = if nullability == walker::Nullability::Unspecified {
+                    t.nullability()
+                } else {
+                    nullability
+                };
+                Type::read_nullable(&t.modified_ty(), name, n, config)
             },
             TypeKind::Elaborated => {
-                Type::read(&t.named_type().unwrap(), name, nonnull)
+                Type::read_nullable(&t.named_type().unwrap(), name, nullability, config)
             },
             TypeKind::Pointer => {
-                Type::Pointer(Box::new(Type::read(&t.pointee(), None, false)), nonnull, t.is_const())
+                let pointee = t.pointee();
+                Type::Pointer(Box::new(Type::read(&pointee, None, false, config)), nonnull, pointee.is_const())
             },
             TypeKind::FunctionProto => {
                 let args =
                     t.function_arg_iter().
-                    map(|a| Type::read(&a, None, false)).collect();
-                Type::FunctionProto(args, Box::new(Type::read(&t.result_type(), None, false)), t.is_variadic())
+                    map(|a| Type::read_param(&a, config)).collect();
+                Type::FunctionProto(args, Box::new(Type::read(&t.result_type(), None, false, config)), t.is_variadic())
+            },
+            // K&R style declarations like void (*)() don't say what they
+            // take, so they're bound as taking nothing.
+            TypeKind::FunctionNoProto => {
+                Type::FunctionProto(Vec::new(), Box::new(Type::read(&t.result_type(), None, false, config)), false)
+            },
+            TypeKind::BlockPointer => {
+                match Type::read(&t.pointee(), None, false, config) {
+                    Type::FunctionProto(args, retty, _) => Type::Block(args, retty, nonnull),
+                    _ => {
+                        println!("Unhandled block type {}", t.spelling());
+                        Type::Void
+                    }
+                }
             },
             TypeKind::ObjCObjectPointer => {
-                Type::Pointer(Box::new(Type::read(&t.pointee(), None, false)), nonnull, false)
+                Type::Pointer(Box::new(Type::read(&t.pointee(), None, false, config)), nonnull, false)
             },
             TypeKind::ObjCSel => Type::SelectorRef,
             TypeKind::ObjCInterface => Type::Class(t.spelling(), Vec::new(), Vec::new()),
@@ -143,7 +238,7 @@ impl Type {
                     },
                     TypeKind::ObjCInterface | TypeKind::ObjCClass => {
                         let typeargs =
-                            t.type_arg_iter().map(|t| Type::read(&t, None, false)).collect();
+                            t.type_arg_iter().map(|t| Type::read(&t, None, false, config)).collect();
                         let proto: Vec<_> =
                             t.protocol_ref_iter().map(|d| d.name()).collect();
                         Type::Class(bt.spelling(), typeargs, proto)
@@ -162,6 +257,7 @@ impl Type {
         match self {
             Type::Void => parse_quote!{ () },
             Type::Bool => parse_quote!{ bool },
+            Type::ObjCBool => parse_quote!{ BOOL },
             Type::Int(true, 1) => parse_quote!{ i8 },
             Type::Int(true, 2) => parse_quote!{ i16 },
             Type::Int(true, 4) => parse_quote!{ i32 },
@@ -174,6 +270,10 @@ impl Type {
             Type::Long(false) => parse_quote!{ usize },
             Type::Float(4) => parse_quote!{ f32 },
             Type::Float(8) => parse_quote!{ f64 },
+            Type::Float(16) => parse_quote!{ LongDouble },
+            Type::CGFloat => parse_quote!{ CGFloat },
+            Type::Complex(4) => parse_quote!{ Complex<f32> },
+            Type::Complex(8) => parse_quote!{ Complex<f64> },
             Type::FixedArray(inner, len) => {
                 let inner_ty = inner.raw_ty();
                 let array_len =
@@ -181,6 +281,25 @@ impl Type {
                                      syn::IntSuffix::None, Span::call_site());
                 parse_quote!{ [#inner_ty; #array_len] }
             },
+            // Rust has no stable SIMD types to match the C vector types'
+            // alignment and calling convention, so this only gets the
+            // elements right. Nothing holding one by value is bound; see
+            // is_vector.
+            Type::Vector(inner, len) => {
+                let inner_ty = inner.raw_ty();
+                let array_len =
+                    syn::LitInt::new(*len,
+                                     syn::IntSuffix::None, Span::call_site());
+                parse_quote!{ [#inner_ty; #array_len] }
+            },
+            Type::IncompleteArray(inner, _, c) => {
+                let inner_ty = inner.raw_ty();
+                if *c {
+                    parse_quote!{ *const #inner_ty }
+                } else {
+                    parse_quote!{ *mut #inner_ty }
+                }
+            },
             Type::Pointer(inner, nonnull, c) => {
                 let inner_ty = if let Type::Void = **inner {
                     parse_quote!{ c_void }
@@ -213,6 +332,7 @@ impl Type {
                 }
                 f
             },
+            Type::Block(..) => parse_quote!{ *mut Block },
             Type::InstanceType(_) => parse_quote!{ Self },
             Type::SelectorRef => parse_quote!{ SelectorRef },
             Type::Id(_) => parse_quote!{ Object },
@@ -233,7 +353,8 @@ impl Type {
     pub fn rust_ty(&self, out: bool) -> syn::Type {
         match self {
             Type::Void => parse_quote!{ () },
-            Type::Bool => parse_quote!{ bool },
+            Type::Bool |
+            Type::ObjCBool => parse_quote!{ bool },
             Type::Int(true, 1) => parse_quote!{ i8 },
             Type::Int(true, 2) => parse_quote!{ i16 },
             Type::Int(true, 4) => parse_quote!{ i32 },
@@ -246,6 +367,10 @@ impl Type {
             Type::Long(false) => parse_quote!{ usize },
             Type::Float(4) => parse_quote!{ f32 },
             Type::Float(8) => parse_quote!{ f64 },
+            Type::Float(16) => parse_quote!{ LongDouble },
+            Type::CGFloat => parse_quote!{ CGFloat },
+            Type::Complex(4) => parse_quote!{ Complex<f32> },
+            Type::Complex(8) => parse_quote!{ Complex<f64> },
             Type::FixedArray(inner, len) => {
                 let inner_ty = inner.rust_ty(out);
                 let array_len =
@@ -253,21 +378,45 @@ impl Type {
                                      syn::IntSuffix::None, Span::call_site());
                 parse_quote!{ [#inner_ty; #array_len] }
             },
+            // Without a length to go with it, all we can do is hand
+            // over a pointer to the first element.
+            Type::IncompleteArray(..) |
+            Type::Vector(..) => self.raw_ty(),
             Type::Pointer(inner, nonnull, _) => {
+                // Callbacks are handed to C as they are, so only their
+                // nullability shows up in the signature.
                 if let Type::FunctionProto(..) = **inner {
-                    return self.raw_ty();
+                    let fn_ty = inner.raw_ty();
+                    return if *nonnull {
+                        fn_ty
+                    } else {
+                        parse_quote!{ Option<#fn_ty> }
+                    };
                 }
                 let inner_ty = if let Type::Void = **inner {
                     parse_quote!{ c_void }
                 } else {
                     inner.rust_ty(true)
                 };
+                if self.is_object_out() {
+                    let obj_ty = match **inner {
+                        Type::Pointer(ref obj, ..) => obj.rust_ty(true),
+                        _ => unreachable!(),
+                    };
+                    return if *nonnull {
+                        parse_quote!{ &mut Option<Arc<#obj_ty>> }
+                    } else {
+                        parse_quote!{ Option<&mut Option<Arc<#obj_ty>>> }
+                    };
+                }
                 let inner_ty = if self.is_objc_object() {
                     if out {
                         parse_quote!{ Arc<#inner_ty> }
                     } else {
                         parse_quote!{ &#inner_ty }
                     }
+                } else if !out && self.is_scalar_out() {
+                    parse_quote!{ &mut #inner_ty }
                 } else {
                     parse_quote!{ &#inner_ty }
                 };
@@ -282,6 +431,19 @@ impl Type {
                     parse_quote!{ Option<#inner_ty> }
                 }
             },
+            // Blocks coming back out are left raw, since there's no
+            // closure behind them to type them with.
+            Type::Block(..) if out => self.raw_ty(),
+            Type::Block(args, retty, nonnull) => {
+                let args: Vec<syn::Type> =
+                    args.iter().map(|arg| arg.raw_ty()).collect();
+                let retty = retty.raw_ty();
+                if *nonnull {
+                    parse_quote!{ &RcBlock<(#(#args,)*), #retty> }
+                } else {
+                    parse_quote!{ Option<&RcBlock<(#(#args,)*), #retty>> }
+                }
+            },
             Type::InstanceType(_) => parse_quote!{ Self },
             Type::SelectorRef => parse_quote!{ SelectorRef },
             Type::Id(_) => parse_quote!{ Object },
@@ -301,16 +463,23 @@ impl Type {
             return;
         }
         match self {
-            Type::FixedArray(inner, _) => inner.refs(list),
+            Type::FixedArray(inner, _) |
+            Type::Vector(inner, _) => inner.refs(list),
+            Type::IncompleteArray(inner, ..) => inner.refs(list),
             Type::Pointer(inner, ..) => inner.refs(list),
+            Type::Typedef(name) |
             Type::Enum(name) |
             Type::Record(name, false) =>
                 list.push(name.clone()),
+            // Protocols share a namespace with classes in Rust, so they're
+            // always referred to by their trait's Proto-suffixed name.
             Type::Id(Some(name)) => {
                 let mut protoname = name.clone();
                 protoname.push_str("Proto");
                 list.push(protoname);
             },
+            // Qualifiers like NSObject<NSCopying> don't show up in the
+            // Rust type, so they aren't needed to bind this.
             Type::Class(name, ta, _pl) => {
                 if name != "Class" && name != "Protocol" {
                     list.push(name.clone());
@@ -318,15 +487,9 @@ impl Type {
                 for t in ta {
                     t.refs(list);
                 }
-                /*
-                for p in pl {
-                    let mut protoname = p.clone();
-                    protoname.push_str("Proto");
-                    list.push(protoname);
-                }
-                */
             },
-            Type::FunctionProto(args, retty, ..) => {
+            Type::FunctionProto(args, retty, ..) |
+            Type::Block(args, retty, _) => {
                 for a in args {
                     a.refs(list);
                 }
@@ -350,9 +513,18 @@ impl Type {
         }
     }
 
+    pub fn is_instancetype(&self) -> bool {
+        match self {
+            Type::Pointer(inner, ..) => inner.is_instancetype(),
+            Type::InstanceType(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn is_anonymous(&self) -> bool {
         match self {
             Type::FixedArray(inner, ..) |
+            Type::IncompleteArray(inner, ..) |
             Type::Pointer(inner, ..) => inner.is_anonymous(),
             Type::Enum(name) |
             Type::Record(name, ..) => name.is_empty(),
@@ -369,9 +541,105 @@ impl Type {
         false
     }
 
+    // x86_64's long double is passed on the x87 stack, which LongDouble
+    // isn't, so like vectors anything passing one by value can't be bound.
+    pub fn is_long_double(&self) -> bool {
+        match self {
+            Type::Float(16) => true,
+            _ => false,
+        }
+    }
+
+    // Whether a value of this type is or holds one pred matches, looking
+    // through arrays, typedefs and records it holds by value. A record
+    // holding a long double is passed the way the long double would be.
+    pub fn holds(&self, decls: &HashMap<String, ItemDecl>, pred: &dyn Fn(&Type) -> bool) -> bool {
+        if pred(self) {
+            return true;
+        }
+        match self {
+            Type::FixedArray(inner, _) => inner.holds(decls, pred),
+            Type::Typedef(name) => match decls.get(name) {
+                Some(ItemDecl::Typedef(t)) => t.ty.holds(decls, pred),
+                _ => false,
+            },
+            Type::Record(name, _) => match decls.get(name) {
+                Some(ItemDecl::Record(r)) => r.fields.iter().any(|(_, t)| t.holds(decls, pred)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Vectors are passed in SIMD registers, which the arrays standing in
+    // for them aren't, so anything passing one by value can't be bound.
+    pub fn is_vector(&self) -> bool {
+        match self {
+            Type::Vector(..) => true,
+            _ => false,
+        }
+    }
+
+    // Pointers to object pointers are out-parameters that hand back an
+    // autoreleased object, if any.
+    pub fn is_object_out(&self) -> bool {
+        match self {
+            Type::Pointer(inner, ..) => inner.is_objc_object(),
+            _ => false,
+        }
+    }
+
+    // Pointers to pointers that aren't objects or callbacks, which go
+    // through a temporary like object out-parameters do.
+    pub fn is_pointer_out(&self) -> bool {
+        match self {
+            Type::Pointer(inner, ..) => match **inner {
+                Type::Pointer(ref pointee, ..) => match **pointee {
+                    Type::FunctionProto(..) => false,
+                    _ => !inner.is_objc_object(),
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // BOOL out-parameters go through a BOOL temporary, since the callee
+    // can write any nonzero byte where BOOL is a signed char, which isn't
+    // a valid bool.
+    pub fn is_bool_out(&self) -> bool {
+        match self {
+            Type::Pointer(inner, _, false) => **inner == Type::ObjCBool,
+            _ => false,
+        }
+    }
+
+    // Pointers to mutable scalars are out-parameters, and get passed as
+    // &mut so the callee's writes are visible.
+    pub fn is_scalar_out(&self) -> bool {
+        match self {
+            Type::Pointer(inner, _, false) => {
+                match **inner {
+                    Type::FixedArray(ref elem, _) => elem.is_copy(),
+                    ref inner => inner.is_copy(),
+                }
+            },
+            _ => false,
+        }
+    }
+
+    pub fn make_nullable(&mut self) {
+        match self {
+            Type::Pointer(_, ref mut nonnull, _) |
+            Type::Block(_, _, ref mut nonnull) => *nonnull = false,
+            _ => (),
+        }
+    }
+
     pub fn is_nonnull(&self) -> bool {
         match self {
-            Type::Pointer(_, nonnull, _) => *nonnull,
+            Type::Pointer(_, nonnull, _) |
+            Type::Block(_, _, nonnull) => *nonnull,
             _ => unreachable!(),
         }
     }
@@ -381,8 +649,38 @@ impl Type {
             Type::Int(..) |
             Type::Long(..) |
             Type::Float(..) |
+            Type::CGFloat |
             Type::Enum(..) |
-            Type::Bool => true,
+            Type::Bool |
+            Type::ObjCBool => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_bool(&self) -> bool {
+        match self {
+            Type::Bool |
+            Type::ObjCBool => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Type::Int(..) |
+            Type::Long(..) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        match self {
+            Type::Int(..) |
+            Type::Long(..) |
+            Type::Float(..) |
+            Type::CGFloat |
+            Type::Bool |
+            Type::ObjCBool => true,
             _ => false,
         }
     }
@@ -391,7 +689,8 @@ impl Type {
         match self {
             Type::Int(signed, _) |
             Type::Long(signed) => *signed,
-            Type::Float(..) => true,
+            Type::Float(..) |
+            Type::CGFloat => true,
             _ => false,
         }
     }
@@ -415,6 +714,21 @@ impl Type {
                             parse_quote!{ #name.as_ref().map_or(ptr::null_mut(), |_| #nonnull_expr) }
                         }
                     },
+                    _ if self.is_bool_out() => {
+                        let nonnull_expr = parse_quote!{ &mut #temp_name as *mut _ };
+                        if *nonnull {
+                            nonnull_expr
+                        } else {
+                            parse_quote!{ #name.as_ref().map_or(ptr::null_mut(), |_| #nonnull_expr) }
+                        }
+                    }
+                    _ if self.is_scalar_out() => {
+                        if *nonnull {
+                            parse_quote!{ #name as *mut _ }
+                        } else {
+                            parse_quote!{ #name.map_or(ptr::null_mut(), |r| r as *mut _) }
+                        }
+                    }
                     _ => {
                         if *nonnull {
                             parse_quote!{ #name as *const _ as *mut _ }
@@ -424,11 +738,54 @@ impl Type {
                     }
                 }
             }
+            Type::Block(_, _, true) => parse_quote!{ #name.as_ptr() },
+            Type::Block(_, _, false) => {
+                parse_quote!{ #name.map_or(ptr::null_mut(), |b| b.as_ptr()) }
+            },
+            Type::ObjCBool => parse_quote!{ BOOL::from(#name) },
             _ => parse_quote!{ #name }
         }
     }
 
+    pub fn slice_ty(&self) -> syn::Type {
+        match self {
+            Type::IncompleteArray(inner, _, c) => {
+                let inner_ty = inner.rust_ty(false);
+                if *c {
+                    parse_quote!{ &[#inner_ty] }
+                } else {
+                    parse_quote!{ &mut [#inner_ty] }
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn slice_to_raw_expr(&self, name: &str) -> syn::Expr {
+        let name = Ident::new(name, Span::call_site());
+        match self {
+            Type::IncompleteArray(_, _, true) =>
+                parse_quote!{ #name.as_ptr() as *const _ },
+            Type::IncompleteArray(_, _, false) =>
+                parse_quote!{ #name.as_mut_ptr() as *mut _ },
+            _ => unreachable!(),
+        }
+    }
+
     pub fn conversion_setup(&self, name: &str) -> Option<syn::Stmt> {
+        if self.is_bool_out() {
+            let temp_name = Ident::new(&format!("__temp_{}", name), Span::call_site());
+            let name = Ident::new(name, Span::call_site());
+            return if self.is_nonnull() {
+                Some(parse_quote!{
+                    let mut #temp_name = BOOL::from(*#name);
+                })
+            } else {
+                Some(parse_quote!{
+                    let mut #temp_name = BOOL::from(#name.as_ref().map_or(false, |b| **b));
+                })
+            };
+        }
         match self {
             Type::Pointer(inner, ..) => {
                 match **inner {
@@ -448,38 +805,169 @@ impl Type {
         }
     }
 
-    pub fn msg_send(&self) -> &'static str {
+    pub fn conversion_finish(&self, name: &str) -> Option<syn::Stmt> {
+        let mut temp_name = "__temp_".to_owned();
+        temp_name.push_str(name);
+        let temp_name = Ident::new(&temp_name, Span::call_site());
+        let name = Ident::new(name, Span::call_site());
+        let store: syn::Block = if self.is_object_out() {
+            parse_quote!({
+                if !#temp_name.is_null() {
+                    objc_retain(#temp_name as *mut _);
+                }
+                *#name = Arc::new(#temp_name);
+            })
+        } else if self.is_bool_out() {
+            parse_quote!({
+                *#name = bool_from_objc(#temp_name);
+            })
+        } else if self.is_pointer_out() {
+            // Other pointers are handed back as references. A nonnull one
+            // can't be set to null, so the caller's is left alone if
+            // nothing was written.
+            let nonnull = match self {
+                Type::Pointer(inner, ..) => inner.is_nonnull(),
+                _ => unreachable!(),
+            };
+            if nonnull {
+                parse_quote!({
+                    if !#temp_name.is_null() {
+                        *#name = &*#temp_name;
+                    }
+                })
+            } else {
+                parse_quote!({
+                    *#name = #temp_name.as_ref();
+                })
+            }
+        } else {
+            return None;
+        };
+        if self.is_nonnull() {
+            Some(parse_quote!{ #store })
+        } else {
+            Some(parse_quote!{
+                if let Some(#name) = #name #store
+            })
+        }
+    }
+
+    pub fn msg_send(&self) -> syn::Expr {
+        match self {
+            Type::Float(..) |
+            Type::CGFloat => {
+                let ty = self.raw_ty();
+                parse_quote!(float_msg_send::<#ty>())
+            },
+            // Whether a struct comes back in registers or through a hidden
+            // pointer depends on its size and the target, so that's left
+            // for the runtime to pick when the bindings are compiled.
+            Type::Record(..) => {
+                let ty = self.raw_ty();
+                parse_quote!(struct_msg_send::<#ty>())
+            },
+            _ => parse_quote!(objc_msgSend as *const u8),
+        }
+    }
+
+    // There's no fpret for super sends, since the receiver can't be nil.
+    pub fn super_msg_send(&self) -> syn::Expr {
         match self {
-            Type::Float(4) | Type::Float(8) => "objc_msgSend_fpret",
-            _ => "objc_msgSend",
+            Type::Record(..) => {
+                let ty = self.raw_ty();
+                parse_quote!(struct_super_msg_send::<#ty>())
+            },
+            _ => parse_quote!(objc_msgSendSuper2 as *const u8),
         }
     }
 }
 
 #[derive(Debug)]
 struct PropertyDecl {
+    name: String,
     ty: Type,
     getter: String,
     setter: Option<String>,
+    weak: bool,
     getter_method: Option<MethodDecl>,
     setter_method: Option<MethodDecl>,
 }
 
 impl PropertyDecl {
-    pub fn read(c: &walker::Cursor) -> PropertyDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> PropertyDecl {
         let setter = if !c.property_attributes().readonly() {
             Some(c.setter_name())
         } else {
             None
         };
         PropertyDecl {
-            ty: Type::read(&c.ty(), None, false),
+            name: c.name(),
+            ty: Type::read(&c.ty(), None, false, config),
             getter: c.getter_name(),
             setter: setter,
+            weak: c.property_attributes().weak(),
             getter_method: None,
             setter_method: None,
         }
     }
+
+    // A property can be redeclared readwrite in a category or class
+    // extension, in which case the merged property gets a setter.
+    pub fn merge(&mut self, other: PropertyDecl) {
+        if self.setter.is_none() {
+            self.setter = other.setter;
+        }
+    }
+
+    pub fn set_getter(&mut self, mut decl: MethodDecl, config: &Config) {
+        // The class keeps weak properties in its own weak storage, so the
+        // accessors don't need anything special beyond the value possibly
+        // going away.
+        if self.weak {
+            decl.retty.make_nullable();
+        }
+        if config.property_accessors {
+            decl.rustname = self.accessor_name();
+        }
+        self.getter_method = Some(decl);
+    }
+
+    pub fn set_setter(&mut self, mut decl: MethodDecl, config: &Config) {
+        if config.property_accessors {
+            decl.rustname = format!("set_{}", self.accessor_name().trim_end_matches('_'));
+        }
+        self.setter_method = Some(decl);
+    }
+
+    // The property's name in snake case, for Config::property_accessors.
+    // Booleans named like their isX getter lose the "is", as the setter
+    // would be set_is_x otherwise.
+    fn accessor_name(&self) -> String {
+        let mut name = self.name.as_str();
+        if self.ty.is_bool() && name.starts_with("is") &&
+           name[2..].starts_with(|c: char| c.is_ascii_uppercase()) {
+            name = &name[2..];
+        }
+        let mut name = snake_case_selector(name);
+        if is_reserved_keyword(&name) {
+            name.push('_');
+        }
+        name
+    }
+}
+
+// ARC's method families that return +1 without saying so. init gets an
+// implicit ns_returns_retained from clang, but the rest don't.
+fn returns_retained_by_family(sel: &str) -> bool {
+    ["alloc", "copy", "mutableCopy", "new"].iter().any(|family| in_family(sel, family))
+}
+
+// Whether a selector is in a method family, i.e. its first word (after
+// any leading underscores) is the family's name.
+fn in_family(sel: &str, family: &str) -> bool {
+    let name = sel.trim_start_matches('_');
+    name.starts_with(family) &&
+        !name[family.len()..].starts_with(|c: char| c.is_ascii_lowercase())
 }
 
 // List of reserved keywords in Rust that are not unusable in ObjC
@@ -487,6 +975,11 @@ fn is_reserved_keyword(s: &str) -> bool {
     match s {
         "as" |
         "create" |
+        "crate" |
+        "dyn" |
+        "in" |
+        "move" |
+        "try" |
         "false" |
         "fn" |
         "impl" |
@@ -526,6 +1019,8 @@ fn is_reserved_keyword(s: &str) -> bool {
 struct Arg {
     name: String,
     ty: Type,
+    // The callee takes over a reference to the object (NS_CONSUMED).
+    consumed: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -535,18 +1030,58 @@ enum ReturnOwnership {
     Autoreleased,
 }
 
-fn bind_availability(c: &walker::Cursor) -> walker::Availability {
+fn bind_availability(c: &walker::Cursor, config: &Config) -> walker::Availability {
     let mut avail = c.availability();
     if let walker::Availability::Available = avail {
         let attrs = c.availability_attrs();
-        let swift_attr = attrs.iter().find(|a| a.platform == "swift" && a.unavailable);
-        if let Some(attr) = swift_attr {
+        // Catches API_UNAVAILABLE for the platform being bound even when
+        // clang's own verdict was for some other platform.
+        let platform = config.platform();
+        let unavailable = attrs.iter().find(|a| {
+            (a.platform == "swift" || a.platform == platform) && a.unavailable
+        });
+        if let Some(attr) = unavailable {
             avail = walker::Availability::NotAvailable(attr.message.clone());
         }
     }
+    if let walker::Availability::NotAvailable(_) = avail {
+        return avail;
+    }
+    if let Some((major, minor)) = introduced_after_target(c, config) {
+        avail = walker::Availability::NotAvailable(
+            format!("Introduced in {} {}.{}", config.platform(), major, minor));
+    }
     avail
 }
 
+// The version something was introduced in on the platform being bound,
+// if that's newer than the deployment target. Unset minor versions are
+// zero, as in API_AVAILABLE(macos(11)).
+fn introduced_after_target(c: &walker::Cursor, config: &Config) -> Option<(u32, u32)> {
+    introduced_after(&c.availability_attrs(), config)
+}
+
+fn introduced_after(attrs: &[walker::AvailabilityAttr], config: &Config) -> Option<(u32, u32)> {
+    let target = config.deployment_target?;
+    let platform = config.platform();
+    attrs.iter().
+        filter(|a| a.platform == platform && a.introduced.Major >= 0).
+        map(|a| (a.introduced.Major as u32, a.introduced.Minor.max(0) as u32)).
+        find(|v| *v > target)
+}
+
+// Deprecated APIs are still bound, but warn when used from Rust the way
+// they would from C. gen_ast allows `deprecated` within the generated code.
+fn deprecated_attr(avail: &walker::Availability) -> Option<syn::Attribute> {
+    match avail {
+        walker::Availability::Deprecated(msg) if msg.is_empty() =>
+            Some(parse_quote!(#[deprecated])),
+        walker::Availability::Deprecated(msg) =>
+            Some(parse_quote!(#[deprecated(note = #msg)])),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 struct MethodDecl {
     rustname: String,
@@ -556,11 +1091,53 @@ struct MethodDecl {
     ret_own: ReturnOwnership,
     inter_ptr: bool,
     consumes_self: bool,
+    // Declared under @optional in a protocol, so conforming classes don't
+    // have to implement it.
+    optional: bool,
+    variadic: bool,
+    // The variadic arguments end with a nil, as marked by
+    // NS_REQUIRES_NIL_TERMINATION.
+    nil_terminated: bool,
+    // Marked NS_DESIGNATED_INITIALIZER, so it's the one a subclass has to
+    // override.
+    designated: bool,
+}
+
+// Whether a parameter name says it's an element count, going by whole
+// words so that e.g. maxLength or numberOfBytesPerRow aren't taken for
+// one: count, len, n, objectCount or numItems.
+fn is_count_name(name: &str) -> bool {
+    let snake = snake_case_selector(name.trim_end_matches('_'));
+    let words: Vec<&str> = snake.split('_').collect();
+    match words.as_slice() {
+        [word] => ["count", "cnt", "length", "len", "n"].contains(word),
+        [first, .., last] =>
+            (["count", "cnt"].contains(last) && !["max", "min"].contains(first)) ||
+            *first == "num",
+        [] => false,
+    }
+}
+
+// Numbers a repeated name, skipping any numbered name that another
+// parameter already has.
+fn unique_arg_name(name: String, seen: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut n = 2;
+    while seen.contains(&unique) {
+        unique = format!("{}_{}", name, n);
+        n += 1;
+    }
+    seen.insert(unique.clone());
+    unique
 }
 
 impl MethodDecl {
-    pub fn read(c: &walker::Cursor) -> MethodDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> MethodDecl {
         let len = c.num_args();
+        // Selectors can repeat a parameter name, as in AppKit's
+        // renameFontCollectionWithName:visibility:toName:error:, so later
+        // ones get numbered from _2 on.
+        let mut seen = HashSet::new();
         let args: Vec<_> =
             (0..len).map(|x| {
                 let arg = c.arg(x);
@@ -568,14 +1145,29 @@ impl MethodDecl {
                 if is_reserved_keyword(&name) {
                     name.push('_');
                 }
+                let name = unique_arg_name(name, &mut seen);
+                let mut consumed = false;
+                arg.visit_children(|c| {
+                    if c.kind() == CursorKind::NSConsumed {
+                        consumed = true;
+                    }
+                    walker::ChildVisit::Continue
+                });
                 Arg {
                     name: name,
-                    ty: Type::read(&arg.ty(), None, false),
+                    ty: Type::read_param(&arg.ty(), config),
+                    consumed: consumed,
                 }
             }).collect();
-        let mut ownership = ReturnOwnership::Autoreleased;
+        let mut ownership = if returns_retained_by_family(&c.name()) {
+            ReturnOwnership::Retained
+        } else {
+            ReturnOwnership::Autoreleased
+        };
         let mut inter_ptr = false;
         let mut consumes_self = false;
+        let mut nil_terminated = false;
+        let mut designated = false;
         c.visit_children(|c| {
             match c.kind() {
                 CursorKind::NSReturnsRetained =>
@@ -584,26 +1176,49 @@ impl MethodDecl {
                     ownership = ReturnOwnership::NotRetained,
                 CursorKind::NSReturnsAutoreleased =>
                     ownership = ReturnOwnership::Autoreleased,
+                CursorKind::UnexposedAttr => {
+                    // objc_method_family(...) overrides the family the
+                    // selector would otherwise be in.
+                    let tokens = c.tokens();
+                    if let Some(i) = tokens.iter().position(|t| t == "objc_method_family" || t == "NS_METHOD_FAMILY") {
+                        if let Some(family) = tokens[i + 1..].iter().find(|t| *t != "(") {
+                            ownership = if returns_retained_by_family(family) {
+                                ReturnOwnership::Retained
+                            } else {
+                                ReturnOwnership::Autoreleased
+                            };
+                        }
+                    }
+                    if tokens.iter().any(|t| t == "NS_REQUIRES_NIL_TERMINATION" || t == "sentinel") {
+                        nil_terminated = true;
+                    }
+                }
                 CursorKind::ObjCReturnsInnerPointer =>
                     inter_ptr = true,
                 CursorKind::NSConsumesSelf =>
                     consumes_self = true,
+                CursorKind::ObjCDesignatedInitializer =>
+                    designated = true,
                 _ => (),
             }
             walker::ChildVisit::Continue
         });
-        let mut rustname = c.name().replace(":", "_");
+        let mut rustname = config.rust_name(&c.name(), NameKind::Method);
         if is_reserved_keyword(&rustname) {
             rustname.push('_');
         }
         MethodDecl {
             rustname: rustname,
-            avail: bind_availability(c),
+            avail: bind_availability(c, config),
             args: args,
-            retty: Type::read(&c.result_ty(), None, false),
+            retty: Type::read(&c.result_ty(), None, false, config),
             ret_own: ownership,
             inter_ptr: inter_ptr,
             consumes_self: consumes_self,
+            optional: c.is_objc_optional(),
+            variadic: c.is_variadic(),
+            nil_terminated: nil_terminated,
+            designated: designated,
         }
     }
     pub fn refs(&self) -> Vec<String> {
@@ -614,66 +1229,467 @@ impl MethodDecl {
         self.retty.refs(&mut refs);
         refs
     }
-    pub fn gen_call(&self, decls: &HashMap<String, ItemDecl>, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+    // An array argument followed by its element count is taken as a
+    // slice, and the count is filled in from the slice's length.
+    pub fn is_slice_arg(&self, i: usize) -> bool {
+        if let Type::IncompleteArray(..) = self.args[i].ty {
+            if let Some(next) = self.args.get(i + 1) {
+                return next.ty.is_integer() && is_count_name(&next.name);
+            }
+        }
+        false
+    }
+    pub fn is_slice_len_arg(&self, i: usize) -> bool {
+        i > 0 && self.is_slice_arg(i - 1)
+    }
+    pub fn is_bindable(&self, decls: &HashMap<String, ItemDecl>) -> bool {
         if let walker::Availability::NotAvailable(_) = self.avail {
-            return None;
+            return false;
         }
         for r in &self.refs() {
+            // id<Proto> is an &Object either way, so a protocol that's only
+            // ever forward declared doesn't stop the method from binding.
+            if r.ends_with("Proto") {
+                continue;
+            }
             if !decls.contains_key(r) && r != "NSString" {
                 println!("Skipping {:?} due to reference to {}", self, r);
-                return None;
+                return false;
             }
         }
-        if self.args.iter().any(|a| a.ty.is_va_list()) {
+        (!self.variadic || self.is_object_list()) &&
+            !self.args.iter().any(|a| a.ty.is_va_list() || a.ty.holds(decls, &Type::is_vector)) &&
+            !self.retty.holds(decls, &Type::is_vector) &&
+            !self.args.iter().any(|a| a.ty.holds(decls, &Type::is_long_double)) &&
+            !self.retty.holds(decls, &Type::is_long_double)
+    }
+
+    // Variadic methods taking a nil-terminated list of objects, whose only
+    // fixed argument is the first object, like +arrayWithObjects:. These
+    // take a slice instead. Other variadic methods, mostly ones taking a
+    // format string, aren't bound.
+    fn is_object_list(&self) -> bool {
+        self.variadic && self.nil_terminated &&
+            self.args.len() == 1 && self.args[0].ty.is_objc_object()
+    }
+    // Methods ending in an NSError** that signal failure by returning NO
+    // or nil, following Cocoa's error handling convention.
+    pub fn returns_error(&self, config: &Config, owner: &str, s: &str) -> bool {
+        let error_arg = match self.args.last() {
+            Some(a) => &a.ty,
+            None => return false,
+        };
+        let is_error = match error_arg {
+            Type::Pointer(inner, ..) => match **inner {
+                Type::Pointer(ref obj, ..) => match **obj {
+                    Type::Class(ref name, ..) => name == "NSError",
+                    _ => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        };
+        is_error &&
+            (self.retty.is_bool() || self.retty.is_objc_object()) &&
+            !config.method_overrides.contains_key(&(owner.to_owned(), s.to_owned()))
+    }
+
+    // Initializers go by the selector's family rather than the name
+    // they're bound under, which a rename can change.
+    fn is_initializer(&self, s: &str) -> bool {
+        self.consumes_self && in_family(s, "init")
+    }
+
+    // The name of an initializer's variant that allocates for itself, like
+    // new... for init..., or try_new... for the checked one.
+    fn init_rustname(&self, new: &str) -> String {
+        if self.rustname.starts_with("init") {
+            self.rustname.replacen("init", new, 1)
+        } else {
+            format!("{}_{}", new, self.rustname)
+        }
+    }
+
+    pub fn gen_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        if self.returns_error(config, owner, s) {
+            return self.gen_result_call(decls, config, owner, s, class);
+        }
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Plain)
+    }
+
+    // The plain binding of a method that gen_call turned into a Result,
+    // as `<name>_raw`, for callers that want to manage the error
+    // themselves.
+    pub fn gen_raw_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        if !self.returns_error(config, owner, s) {
             return None;
         }
-        let initializer = self.consumes_self && self.rustname.starts_with("init");
-        let mname = if initializer {
-            self.rustname.replacen("init", "new", 1)
-        } else {
-            self.rustname.clone()
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Raw)
+    }
+
+    // The binding a type conforming to the protocol this method is in has
+    // to provide, which is the `_raw` one when gen_call wraps it in a
+    // Result. None for optional methods, whose trait methods message the
+    // object by default.
+    pub fn gen_required_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str) -> Option<proc_macro2::TokenStream> {
+        if self.optional {
+            return None;
+        }
+        if self.returns_error(config, owner, s) {
+            self.gen_raw_call(decls, config, owner, s, false)
+        } else {
+            self.gen_call(decls, config, owner, s, false)
+        }
+    }
+
+    // A `try_<name>` variant for optional protocol methods, which checks
+    // respondsToSelector: first and hands back None if the object doesn't
+    // implement the method.
+    pub fn gen_responding_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str) -> Option<proc_macro2::TokenStream> {
+        if !self.optional || (self.is_initializer(s)) {
+            return None;
+        }
+        let plain: syn::TraitItemMethod = syn::parse2(self.gen_call(decls, config, owner, s, false)?).unwrap();
+        let sig = &plain.sig;
+        let name = &sig.ident;
+        let try_name = Ident::new(&format!("try_{}", name), Span::call_site());
+        let unsafety = &sig.unsafety;
+        let generics = &sig.decl.generics;
+        let params = &sig.decl.inputs;
+        let args: Vec<&Ident> = params.iter().filter_map(|p| {
+            match p {
+                syn::FnArg::Captured(syn::ArgCaptured { pat: syn::Pat::Ident(ref p), .. }) => Some(&p.ident),
+                _ => None,
+            }
+        }).collect();
+        let retty: syn::Type = match sig.decl.output {
+            syn::ReturnType::Default => parse_quote!{ () },
+            syn::ReturnType::Type(_, ref ty) => (**ty).clone(),
         };
+        let selname = Ident::new(&format!("SEL_{}", s.replace(":", "_")), Span::call_site());
+        let deprecated = deprecated_attr(&self.avail);
+        Some(quote!{
+            #deprecated
+            #unsafety fn #try_name #generics(#params) -> Option<#retty> {
+                let responds = unsafe {
+                    let send:
+                        unsafe extern "C" fn(
+                            *mut Object,
+                            SelectorRef,
+                            SelectorRef) -> BOOL =
+                        mem::transmute(objc_msgSend as *const u8);
+                    bool_from_objc(send(self as *const Self as *mut Self as *mut _,
+                                        sel!("respondsToSelector:"),
+                                        #selname))
+                };
+                if responds {
+                    Some(self.#name(#(#args),*))
+                } else {
+                    None
+                }
+            }
+        })
+    }
+
+    // Wraps the `_raw` binding, passing it an error slot of its own and
+    // turning NO or nil into the NSError that was left in it. Methods
+    // don't always set one when they fail, so the error is an Option.
+    fn gen_result_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        if config.skip_methods.contains(&(owner.to_owned(), s.to_owned())) ||
+           !self.is_bindable(decls) {
+            return None;
+        }
+        let initializer = self.is_initializer(s);
+        let name = if initializer {
+            self.init_rustname("new")
+        } else {
+            self.rustname.clone()
+        };
+        let raw_name = Ident::new(&format!("{}_raw", name.trim_end_matches('_')), Span::call_site());
+        let name = Ident::new(&name, Span::call_site());
+        let mut params = self.rust_params();
+        params.pop();
+        if !initializer && !class {
+            params.insert(0, parse_quote!{ &self });
+        }
+        let error_ty = &self.args.last().unwrap().ty;
+        let err_ty: syn::Type = match error_ty {
+            Type::Pointer(inner, ..) => match **inner {
+                Type::Pointer(ref obj, ..) => {
+                    let obj_ty = obj.rust_ty(true);
+                    parse_quote!{ Option<Arc<#obj_ty>> }
+                },
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let error_arg: syn::Expr = if error_ty.is_nonnull() {
+            parse_quote!{ &mut _error }
+        } else {
+            parse_quote!{ Some(&mut _error) }
+        };
+        let mut args: Vec<syn::Expr> =
+            (0..self.args.len() - 1).
+            filter(|i| !self.is_slice_len_arg(*i)).
+            map(|i| {
+                let name = Ident::new(&self.args[i].name, Span::call_site());
+                parse_quote!{ #name }
+            }).collect();
+        args.push(error_arg);
+        let receiver: syn::Expr = if initializer || class {
+            parse_quote!{ Self }
+        } else {
+            parse_quote!{ self }
+        };
+        let call: syn::Expr = if initializer || class {
+            parse_quote!{ #receiver::#raw_name(#(#args),*) }
+        } else {
+            parse_quote!{ #receiver.#raw_name(#(#args),*) }
+        };
+        let deprecated = deprecated_attr(&self.avail);
+        if self.retty.is_bool() {
+            return Some(quote!{
+                #deprecated
+                fn #name(#(#params),*) -> Result<(), #err_ty> {
+                    let mut _error = None;
+                    if #call {
+                        Ok(())
+                    } else {
+                        Err(_error)
+                    }
+                }
+            });
+        }
+        let mut ok_ty = self.retty.clone();
+        if let Type::Pointer(_, ref mut nonnull, _) = ok_ty {
+            *nonnull = true;
+        }
+        let ok_ty = ok_ty.rust_ty(true);
+        Some(quote!{
+            #deprecated
+            fn #name(#(#params),*) -> Result<#ok_ty, #err_ty> {
+                let mut _error = None;
+                match #call {
+                    Some(_ret) => Ok(_ret),
+                    None => Err(_error),
+                }
+            }
+        })
+    }
+
+    fn rust_params(&self) -> Vec<syn::FnArg> {
+        (&self.args).iter().enumerate().
+            filter(|(i, _)| !self.is_slice_len_arg(*i)).
+            map(|(i, a)| {
+                let name = Ident::new(&a.name, Span::call_site());
+                let rawty = if self.is_slice_arg(i) {
+                    a.ty.slice_ty()
+                } else {
+                    a.ty.rust_ty(false)
+                };
+                parse_quote!{ #name : #rawty }
+            }).collect()
+    }
+
+    // An extra `<name>_autoreleased` variant for instance methods with
+    // autoreleased object returns, that hands back a plain reference
+    // instead of retaining it, for call chains inside an autorelease pool.
+    // It's unsafe since the reference dies with the pool, not with self.
+    pub fn gen_borrowed_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        if !config.borrowed_returns ||
+           self.ret_own != ReturnOwnership::Autoreleased ||
+           !self.retty.is_objc_object() ||
+           self.inter_ptr ||
+           class ||
+           (self.is_initializer(s)) ||
+           config.method_overrides.contains_key(&(owner.to_owned(), s.to_owned())) {
+            return None;
+        }
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Borrowed)
+    }
+
+    // An extra `try_new...` variant for initializers annotated nonnull,
+    // which checks for nil anyway. Failable initializers are often
+    // annotated wrong, and the plain variant can't tell.
+    pub fn gen_checked_init(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        let initializer = self.is_initializer(s);
+        let checkable = initializer && !class &&
+            self.retty.is_objc_object() && self.retty.is_nonnull() &&
+            config.assume_nonnull_annotations &&
+            !config.method_overrides.contains_key(&(owner.to_owned(), s.to_owned()));
+        if !checkable {
+            return None;
+        }
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Checked)
+    }
+
+    // The init... method itself, taking an object from alloc(), next to
+    // the new... one that allocates for itself. The Allocated is consumed
+    // along with its reference, so nothing can be initialized twice or
+    // used uninitialized.
+    pub fn gen_alloced_init(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        let initializer = self.is_initializer(s);
+        if !initializer || class ||
+           self.returns_error(config, owner, s) ||
+           config.method_overrides.contains_key(&(owner.to_owned(), s.to_owned())) {
+            return None;
+        }
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Alloced)
+    }
+
+    // An extra `super_<name>` variant for instance methods that calls the
+    // superclass' implementation, for subclasses forwarding to it from
+    // their overrides. It takes the overriding class, since the lookup
+    // has to start above it rather than above the class declaring the
+    // method. Only designated initializers, which subclasses have to
+    // override, get one unless Config::super_calls is set. It's unsafe
+    // since super_init... consumes the receiver's reference the way
+    // -init does, and nothing checks the class.
+    pub fn gen_super_call(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool) -> Option<proc_macro2::TokenStream> {
+        if class || !(self.designated || config.super_calls) ||
+           config.method_overrides.contains_key(&(owner.to_owned(), s.to_owned())) {
+            return None;
+        }
+        self.gen_call_inner(decls, config, owner, s, class, CallVariant::Super)
+    }
+
+    fn gen_call_inner(&self, decls: &HashMap<String, ItemDecl>, config: &Config, owner: &str, s: &str, class: bool, variant: CallVariant) -> Option<proc_macro2::TokenStream> {
+        let borrowed = variant == CallVariant::Borrowed;
+        let to_super = variant == CallVariant::Super;
+        let alloced = variant == CallVariant::Alloced;
+        let key = (owner.to_owned(), s.to_owned());
+        if config.skip_methods.contains(&key) {
+            return None;
+        }
+        if !self.is_bindable(decls) {
+            return None;
+        }
+        let mut retty = self.retty.clone();
+        // Failing with an error hands back nil, whatever the annotations say.
+        if (!config.assume_nonnull_annotations ||
+            variant == CallVariant::Checked ||
+            variant == CallVariant::Raw) &&
+           retty.is_objc_object() {
+            retty.make_nullable();
+        }
+        let initializer = self.is_initializer(s);
+        let mname = if variant == CallVariant::Checked {
+            self.init_rustname("try_new")
+        } else if to_super {
+            format!("super_{}", self.rustname)
+        } else if alloced {
+            self.rustname.clone()
+        } else if variant == CallVariant::Raw && initializer {
+            format!("{}_raw", self.init_rustname("new").trim_end_matches('_'))
+        } else if variant == CallVariant::Raw {
+            format!("{}_raw", self.rustname.trim_end_matches('_'))
+        } else if initializer {
+            self.init_rustname("new")
+        } else if borrowed {
+            format!("{}_autoreleased", self.rustname)
+        } else {
+            self.rustname.clone()
+        };
+        // Freshly created objects are usually dropped by mistake, unlike
+        // getters that hand back something the receiver keeps anyway. That
+        // goes for anything named like it makes one too, even if it isn't
+        // annotated as handing back its own reference.
+        let must_use = if retty.is_objc_object() && !borrowed &&
+                          (initializer ||
+                           self.ret_own == ReturnOwnership::Retained ||
+                           returns_retained_by_family(s)) {
+            quote!(#[must_use])
+        } else {
+            quote!()
+        };
+        let deprecated = deprecated_attr(&self.avail);
         let mname = Ident::new(&mname, Span::call_site());
         let mut selname = "SEL_".to_owned();
         selname.push_str(&s.replace(":", "_"));
         let selname =
             Ident::new(&selname, Span::call_site());
-        let mut params: Vec<syn::FnArg> =
-            (&self.args).iter().
-            map(|a| {
-                let name = Ident::new(&a.name, Span::call_site());
-                let rawty = a.ty.rust_ty(false);
-                parse_quote!{ #name : #rawty }
-            }).collect();
-        if !initializer && !class {
+        let mut params = self.rust_params();
+        if self.is_object_list() {
+            let name = Ident::new(&self.args[0].name, Span::call_site());
+            params = vec![parse_quote!{ #name: &[&Object] }];
+        }
+        if (!initializer || to_super) && !class {
             params.insert(0, parse_quote!{ &self });
         }
+        if alloced {
+            params.insert(0, parse_quote!{ this: Allocated<Self> });
+        }
+        if to_super {
+            params.insert(1, parse_quote!{ caller: ClassRef });
+        }
         let params = &params;
         let rawtypes: Vec<_> =
             (&self.args).iter().map(|a| a.ty.raw_ty()).collect();
-        let raw_ret_ty = self.retty.raw_ty();
-        let rust_ret_ty = if self.retty.is_objc_object() || self.inter_ptr {
-            self.retty.rust_ty(true)
+        let raw_ret_ty = retty.raw_ty();
+        let rust_ret_ty = if borrowed {
+            retty.rust_ty(false)
+        } else if retty.is_objc_object() || self.inter_ptr || retty == Type::ObjCBool {
+            retty.rust_ty(true)
+        } else {
+            retty.raw_ty()
+        };
+        let msgsend = if to_super {
+            retty.super_msg_send()
         } else {
-            self.retty.raw_ty()
+            retty.msg_send()
+        };
+        let receiver_ty: syn::Type = if to_super {
+            parse_quote!(*const Super)
+        } else {
+            parse_quote!(*mut Object)
         };
-        let msgsend =
-            Ident::new(self.retty.msg_send(), Span::call_site());
         let args: Vec<syn::Expr> =
-            (&self.args).iter().
-            map(|a| a.ty.to_raw_expr(&a.name)).collect();
+            (&self.args).iter().enumerate().
+            map(|(i, a)| {
+                if self.is_slice_arg(i) {
+                    a.ty.slice_to_raw_expr(&a.name)
+                } else if self.is_slice_len_arg(i) {
+                    let slice = Ident::new(&self.args[i - 1].name, Span::call_site());
+                    parse_quote!{ #slice.len() as _ }
+                } else {
+                    a.ty.to_raw_expr(&a.name)
+                }
+            }).collect();
         let setup: Vec<_> =
             (&self.args).iter().
             filter_map(|a| a.ty.conversion_setup(&a.name)).collect();
+        // Arguments are only borrowed, so consumed ones get an extra
+        // reference for the callee to take.
+        let consume: Vec<syn::Stmt> =
+            (&self.args).iter().enumerate().
+            filter(|(_, a)| a.consumed && a.ty.is_objc_object()).
+            map(|(i, a)| {
+                let rawty = &rawtypes[i];
+                let raw = a.ty.to_raw_expr(&a.name);
+                parse_quote!{
+                    {
+                        let _consumed: #rawty = #raw;
+                        objc_retain(_consumed as *mut Object);
+                    }
+                }
+            }).collect();
+        let writeback: Vec<_> =
+            (&self.args).iter().
+            filter_map(|a| a.ty.conversion_finish(&a.name)).collect();
         let mut finish: Vec<syn::Stmt> = Vec::new();
+        if retty == Type::ObjCBool {
+            finish.push(parse_quote!{
+                let _ret = bool_from_objc(_ret);
+            });
+        }
         if ReturnOwnership::Autoreleased == self.ret_own &&
-           self.retty.is_objc_object() {
+           retty.is_objc_object() && !borrowed {
             finish.push(parse_quote!{
-                objc_retainAutoreleasedReturnValue(_ret as *mut _);
+                retain_autoreleased!(_ret as *mut _);
             });
         }
-        if self.retty.is_objc_object() {
-            if self.retty.is_nonnull() {
+        if retty.is_objc_object() && !borrowed {
+            if retty.is_nonnull() {
                 finish.push(parse_quote!{
                     let _ret = Arc::new_unchecked(_ret);
                 });
@@ -682,8 +1698,8 @@ impl MethodDecl {
                     let _ret = Arc::new(_ret);
                 });
             }
-        } else if self.inter_ptr {
-            if self.retty.is_nonnull() {
+        } else if self.inter_ptr || borrowed {
+            if retty.is_nonnull() {
                 finish.push(parse_quote!{
                     let _ret = &*_ret;
                 });
@@ -698,28 +1714,118 @@ impl MethodDecl {
             }
         }
         let get_obj: syn::Expr =
-            if class {
+            if to_super {
+                parse_quote!(&Super {
+                    receiver: self as *const Self as *mut Self as *mut _,
+                    class: caller.0,
+                })
+            } else if class {
                 parse_quote!(<Self as ObjCClass>::classref().0 as *const Object as *mut _)
+            } else if alloced {
+                parse_quote!(this.into_raw() as *mut _)
             } else if initializer {
-                parse_quote!(objc_allocWithZone(<Self as ObjCClass>::classref()))
+                parse_quote!(alloc_with_zone(<Self as ObjCClass>::classref()))
             } else {
                 parse_quote!(self as *const Self as *mut Self as *mut _)
             };
+        if let Some(body) = config.method_overrides.get(&key) {
+            let body: syn::Block = syn::parse_str(body).unwrap_or_else(|e| {
+                panic!("Bad override for {} {}: {}", owner, s, e)
+            });
+            return Some(quote!{
+                #must_use
+                #deprecated
+                fn #mname(#(#params),*) -> #rust_ret_ty #body
+            });
+        }
+        let unsafety = if borrowed || to_super {
+            quote!(unsafe)
+        } else {
+            quote!()
+        };
+        // Lists with a ...count: counterpart, like +arrayWithObjects:count:,
+        // go through it, so there's no limit on their length. The rest
+        // can only take so many objects through the variadic call.
+        let counted = format!("{}count:", s);
+        let has_counted = match decls.get(owner) {
+            Some(ItemDecl::Class(c)) if class => c.find_cmethod(decls, &counted).is_some(),
+            Some(ItemDecl::Class(c)) => c.find_imethod(decls, &counted).is_some(),
+            _ => false,
+        };
+        if self.is_object_list() && has_counted {
+            let list = Ident::new(&self.args[0].name, Span::call_site());
+            return Some(quote!{
+                #must_use
+                #deprecated
+                #unsafety fn #mname(#(#params),*) -> #rust_ret_ty {
+                    unsafe {
+                        let send:
+                            unsafe extern "C" fn(
+                                #receiver_ty,
+                                SelectorRef,
+                                *const *const Object,
+                                usize) -> #raw_ret_ty =
+                            mem::transmute(#msgsend);
+                        let _ret = send(
+                            #get_obj,
+                            sel!(#counted),
+                            #list.as_ptr() as *const *const Object,
+                            #list.len()
+                        );
+                        #(#finish)*
+                        _ret
+                    }
+                }
+            });
+        }
+        if self.is_object_list() {
+            let list = Ident::new(&self.args[0].name, Span::call_site());
+            return Some(quote!{
+                /// # Safety
+                ///
+                /// The objects are passed through a C variadic call, which
+                /// can take at most 16 of them. Passing more panics.
+                #must_use
+                #deprecated
+                unsafe fn #mname(#(#params),*) -> #rust_ret_ty {
+                    unsafe {
+                        let send:
+                            unsafe extern "C" fn(
+                                #receiver_ty,
+                                SelectorRef,
+                                *mut Object, ...) -> #raw_ret_ty =
+                            mem::transmute(#msgsend);
+                        let _ret = send_object_list(
+                            send,
+                            #get_obj,
+                            #selname,
+                            #list
+                        );
+                        #(#finish)*
+                        _ret
+                    }
+                }
+            });
+        }
         Some(quote!{
-            fn #mname(#(#params),*) -> #rust_ret_ty {
+            #must_use
+            #deprecated
+            #unsafety fn #mname(#(#params),*) -> #rust_ret_ty {
                 #(#setup)*
                 unsafe {
+                    #(#consume)*
                     let send:
                         unsafe extern "C" fn(
-                            *mut Object,
+                            #receiver_ty,
                             SelectorRef,
                             #(#rawtypes),*) -> #raw_ret_ty =
-                        mem::transmute(#msgsend as *const u8);
+                        mem::transmute(#msgsend);
                     let _ret = send(
                         #get_obj,
                         #selname,
                         #(#args),*
                     );
+                    #(#writeback)*
                     #(#finish)*
                     _ret
                 }
@@ -728,80 +1834,105 @@ impl MethodDecl {
     }
 }
 
+// The flavors of binding gen_call_inner can generate for a method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CallVariant {
+    Plain,
+    Borrowed,
+    Checked,
+    Super,
+    Raw,
+    Alloced,
+}
+
 #[derive(Debug)]
 struct ClassDecl {
     src: PathBuf,
     rustname: String,
     superclass: String,
-    size: u64,
+    // None when the layout isn't known, e.g. categories and protocols, or
+    // classes without a complete @interface.
+    size: Option<u64>,
+    // Marked __attribute__((objc_exception)), which subclasses of an
+    // exception class don't need to repeat.
+    exception: bool,
     protocols: Vec<String>,
     cprops: HashMap<String, PropertyDecl>,
     iprops: HashMap<String, PropertyDecl>,
     cmethods: HashMap<String, MethodDecl>,
     imethods: HashMap<String, MethodDecl>,
+    // Name, type and byte offset of each ivar whose offset clang knows.
+    ivars: Vec<(String, Type, u64)>,
 }
 
 impl ClassDecl {
-    pub fn read(c: &walker::Cursor) -> ClassDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> ClassDecl {
         println!("{}", c.name());
         let mut superclass = String::new();
-        let mut protocols = Vec::new();
+        let mut exception = false;
         c.visit_children(|c| {
             match c.kind() {
-                CursorKind::ObjCSuperClassRef => {
-                    superclass = c.name();
-                }
-                CursorKind::ObjCProtocolRef => {
-                    protocols.push(c.name());
-                }
+                CursorKind::ObjCSuperClassRef => superclass = c.name(),
+                CursorKind::ObjCException => exception = true,
                 _ => {}
             }
             return walker::ChildVisit::Continue;
         });
         let size = if c.kind() == CursorKind::ObjCInterfaceDecl {
-            c.ty().size()
+            c.ty().try_size().filter(|s| *s > 0)
         } else {
-            0
+            None
         };
         let mut decl = ClassDecl {
             src: c.location().filename(),
             rustname: c.name(),
             superclass: superclass,
             size: size,
-            protocols: protocols,
+            exception: exception,
+            protocols: Vec::new(),
             cprops: HashMap::new(),
             iprops: HashMap::new(),
             cmethods: HashMap::new(),
             imethods: HashMap::new(),
+            ivars: Vec::new(),
         };
-        decl.read_category(c);
+        decl.read_category(c, config);
         decl
     }
 
-    pub fn read_category(&mut self, c: &walker::Cursor) {
+    pub fn read_category(&mut self, c: &walker::Cursor, config: &Config) {
         c.visit_children(|c| {
-            if let walker::Availability::NotAvailable(_) = bind_availability(&c) {
+            if let walker::Availability::NotAvailable(_) = bind_availability(&c, config) {
                 return walker::ChildVisit::Continue;
             }
             match c.kind() {
                 CursorKind::UnexposedAttr => {
                     println!("Found unexposed attr {}", c.name());
                 }
+                CursorKind::ObjCIvarDecl => {
+                    // Bitfields share their storage, so there's no offset
+                    // that could be handed out for one on its own.
+                    if !c.is_bit_field() {
+                        if let Some(offset) = c.offset_of_field() {
+                            self.ivars.push((c.name(), Type::read(&c.ty(), None, false, config), offset));
+                        }
+                    }
+                }
                 CursorKind::ObjCClassMethodDecl => {
-                    let old = self.cmethods.insert(c.name(), MethodDecl::read(&c));
+                    let old = self.cmethods.insert(c.name(), MethodDecl::read(&c, config));
                     if old.is_some() {
                         panic!("????");
                     }
                 }
                 CursorKind::ObjCInstanceMethodDecl => {
                     let selname = c.name();
-                    let decl = MethodDecl::read(&c);
+                    let decl = MethodDecl::read(&c, config);
                     if let Some(p) = self.iprops.values_mut().find(|p| p.getter == selname) {
-                        p.getter_method = Some(decl);
+                        p.set_getter(decl, config);
                         return walker::ChildVisit::Continue;
                     }
                     if let Some(p) = self.iprops.values_mut().find(|p| p.setter.as_ref() == Some(&selname)) {
-                        p.setter_method = Some(decl);
+                        p.set_setter(decl, config);
                         return walker::ChildVisit::Continue;
                     }
                     let old = self.imethods.insert(selname, decl);
@@ -811,22 +1942,58 @@ impl ClassDecl {
                 }
                 CursorKind::ObjCPropertyDecl => {
                     let classprop = c.property_attributes().class();
-                    let decl = PropertyDecl::read(&c);
+                    let decl = PropertyDecl::read(&c, config);
                     if classprop {
                         let old = self.cprops.insert(c.name(), decl);
                         if old.is_some() {
                             panic!("Duplicate class property declaration");
                         }
-                    } else {
-                        let old = self.iprops.insert(c.name(), decl);
-                        if old.is_some() {
-                            println!("Duplicate property declaration - {} in {}", c.name(), self.rustname);
+                    } else if let Some(p) = self.iprops.get_mut(&c.name()) {
+                        println!("Duplicate property declaration - {} in {}", c.name(), self.rustname);
+                        p.merge(decl);
+                        if p.setter_method.is_none() {
+                            let setter = match p.setter {
+                                Some(ref setter) => self.imethods.remove(setter),
+                                None => None,
+                            };
+                            if let Some(decl) = setter {
+                                p.set_setter(decl, config);
+                            }
                         }
+                    } else {
+                        self.iprops.insert(c.name(), decl);
+                    }
+                    // Accessors synthesized by a class extension's
+                    // readwrite redeclaration show up under the property
+                    // rather than next to it.
+                    if !classprop {
+                        let p = self.iprops.get_mut(&c.name()).unwrap();
+                        c.visit_children(|c| {
+                            if c.kind() == CursorKind::ObjCInstanceMethodDecl {
+                                let selname = c.name();
+                                if p.getter_method.is_none() && p.getter == selname {
+                                    p.set_getter(MethodDecl::read(&c, config), config);
+                                } else if p.setter_method.is_none() && p.setter.as_ref() == Some(&selname) {
+                                    p.set_setter(MethodDecl::read(&c, config), config);
+                                }
+                            }
+                            walker::ChildVisit::Continue
+                        });
                     }
                 }
                 CursorKind::ObjCClassRef => {
                     // Same as ObjCSuperClassRef, right?
                 }
+                CursorKind::ObjCException => {
+                    // Picked up by ClassDecl::read.
+                }
+                CursorKind::ObjCProtocolRef => {
+                    // Categories can add conformances too.
+                    let name = c.name();
+                    if !self.protocols.contains(&name) {
+                        self.protocols.push(name);
+                    }
+                }
                 _ => {
                     println!("Unknown cursor kind {:?}", c.kind());
                 }
@@ -835,6 +2002,133 @@ impl ClassDecl {
         });
     }
 
+    // A rename, snake_case_selector or a property accessor name can give
+    // different selectors the same name, like foo and foo:, which would
+    // then be defined twice. Those fall back to their selector with the
+    // colons as underscores, which can't collide.
+    pub fn resolve_name_collisions(&mut self) {
+        let mut methods: Vec<(&str, &mut MethodDecl)> = Vec::new();
+        for (s, m) in self.cmethods.iter_mut().chain(self.imethods.iter_mut()) {
+            methods.push((s, m));
+        }
+        for p in self.cprops.values_mut().chain(self.iprops.values_mut()) {
+            let PropertyDecl { ref getter, ref setter, ref mut getter_method, ref mut setter_method, .. } = *p;
+            if let Some(m) = getter_method.as_mut() {
+                methods.push((getter, m));
+            }
+            if let (Some(s), Some(m)) = (setter.as_ref(), setter_method.as_mut()) {
+                methods.push((s, m));
+            }
+        }
+        let mut sels: HashMap<String, HashSet<&str>> = HashMap::new();
+        for (s, m) in &methods {
+            sels.entry(m.rustname.clone()).or_default().insert(s);
+        }
+        for (s, m) in methods {
+            if sels[&m.rustname].len() > 1 {
+                println!("{} collides with another method named {}", s, m.rustname);
+                m.rustname = s.replace(":", "_");
+                if is_reserved_keyword(&m.rustname) {
+                    m.rustname.push('_');
+                }
+            }
+        }
+    }
+
+    // Selectors that the protocol traits this class conforms to will
+    // provide, so the class doesn't need to generate them itself.
+    pub fn proto_selectors(&self, decls: &HashMap<String, ItemDecl>) -> HashSet<String> {
+        let mut sels = HashSet::new();
+        for p in &self.all_protocols(decls) {
+            if let Some(ItemDecl::Proto(proto)) = decls.get(&format!("{}Proto", p)) {
+                for (s, m) in &proto.imethods {
+                    if m.is_bindable(decls) {
+                        sels.insert(s.clone());
+                    }
+                }
+            }
+        }
+        sels
+    }
+
+    // Every protocol this class conforms to, including ones adopted by
+    // its superclasses and ones inherited by the protocols themselves.
+    pub fn all_protocols(&self, decls: &HashMap<String, ItemDecl>) -> Vec<String> {
+        let mut protos = Vec::new();
+        self.collect_protocols(decls, &mut protos);
+        protos
+    }
+
+    fn collect_protocols(&self, decls: &HashMap<String, ItemDecl>, protos: &mut Vec<String>) {
+        for p in &self.protocols {
+            if protos.contains(p) {
+                continue;
+            }
+            protos.push(p.clone());
+            if let Some(ItemDecl::Proto(proto)) = decls.get(&format!("{}Proto", p)) {
+                proto.collect_protocols(decls, protos);
+            }
+        }
+        if let Some(ItemDecl::Class(superclass)) = decls.get(&self.superclass) {
+            superclass.collect_protocols(decls, protos);
+        }
+    }
+
+    // The class -copy (or -mutableCopy) is declared to return, which is
+    // the topmost class conforming to NSCopying, or the topmost mutable
+    // subclass of a class conforming to NSMutableCopying. Subclasses
+    // don't have to override them to return their own class.
+    pub fn copy_base(&self, name: &str, decls: &HashMap<String, ItemDecl>, mutable: bool) -> String {
+        let conforms = |c: &ClassDecl, proto: &str| c.all_protocols(decls).iter().any(|p| p == proto);
+        let superclass = match decls.get(&self.superclass) {
+            Some(ItemDecl::Class(sc)) => sc,
+            _ => return name.to_owned(),
+        };
+        let keep_going = if mutable {
+            match decls.get(&superclass.superclass) {
+                Some(ItemDecl::Class(ssc)) => conforms(ssc, "NSMutableCopying"),
+                _ => false,
+            }
+        } else {
+            conforms(superclass, "NSCopying")
+        };
+        if keep_going {
+            superclass.copy_base(&self.superclass, decls, mutable)
+        } else {
+            name.to_owned()
+        }
+    }
+
+    pub fn is_exception(&self, decls: &HashMap<String, ItemDecl>) -> bool {
+        if self.exception {
+            return true;
+        }
+        match decls.get(&self.superclass) {
+            Some(ItemDecl::Class(superclass)) => superclass.is_exception(decls),
+            _ => false,
+        }
+    }
+
+    pub fn find_cmethod<'a>(&'a self, decls: &'a HashMap<String, ItemDecl>, sel: &str) -> Option<&'a MethodDecl> {
+        if let Some(m) = self.cmethods.get(sel) {
+            return Some(m);
+        }
+        match decls.get(&self.superclass) {
+            Some(ItemDecl::Class(superclass)) => superclass.find_cmethod(decls, sel),
+            _ => None,
+        }
+    }
+
+    pub fn find_imethod<'a>(&'a self, decls: &'a HashMap<String, ItemDecl>, sel: &str) -> Option<&'a MethodDecl> {
+        if let Some(m) = self.imethods.get(sel) {
+            return Some(m);
+        }
+        match decls.get(&self.superclass) {
+            Some(ItemDecl::Class(superclass)) => superclass.find_imethod(decls, sel),
+            _ => None,
+        }
+    }
+
     pub fn collect_selectors(&self, h: &mut HashSet<String>) {
         for p in self.iprops.values() {
             h.insert(p.getter.clone());
@@ -855,6 +2149,7 @@ impl ClassDecl {
 struct EnumDecl {
     src: PathBuf,
     rustname: String,
+    avail: walker::Availability,
     ty: Type,
     exhaustive: bool,
     flagenum: bool,
@@ -862,12 +2157,23 @@ struct EnumDecl {
 }
 
 impl EnumDecl {
-    pub fn read(c: &walker::Cursor) -> EnumDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> EnumDecl {
         let mut variants = Vec::new();
-        let ty = Type::read(&c.enum_ty(), None, false);
+        let ty = Type::read(&c.enum_ty(), None, false, config);
         let mut flagenum = false;
+        // NS_CLOSED_ENUM and friends mark enums that will never grow new
+        // values. Everything else may return values we don't know about.
+        let mut exhaustive = c.tokens().iter().any(|t| {
+            t == "NS_CLOSED_ENUM" || t == "CF_CLOSED_ENUM"
+        });
         c.visit_children(|c| {
             match c.kind() {
+                CursorKind::UnexposedAttr => {
+                    let tokens = c.tokens();
+                    if let Some(i) = tokens.iter().position(|t| t == "enum_extensibility") {
+                        exhaustive = tokens[i..].iter().any(|t| t == "closed");
+                    }
+                },
                 CursorKind::EnumConstantDecl => {
                     let (val, neg) = if ty.is_signed() {
                         let val = c.enum_const_value_signed();
@@ -888,7 +2194,7 @@ impl EnumDecl {
                         return walker::ChildVisit::Continue;
                     }
                     variants.push((
-                        c.name(),
+                        config.rust_name(&c.name(), NameKind::EnumVariant),
                         val,
                         neg
                     ));
@@ -903,8 +2209,9 @@ impl EnumDecl {
         EnumDecl {
             src: c.location().filename(),
             rustname: c.name(),
+            avail: bind_availability(c, config),
             ty: ty,
-            exhaustive: false,
+            exhaustive: exhaustive,
             flagenum: flagenum,
             variants: variants,
         }
@@ -915,24 +2222,30 @@ impl EnumDecl {
 struct RecordDecl {
     src: PathBuf,
     rustname: String,
+    avail: walker::Availability,
     fields: Vec<(String, Type)>,
     union: bool,
+    boxable_encoding: Option<String>,
 }
 
 impl RecordDecl {
-    pub fn read(c: &walker::Cursor) -> Vec<RecordDecl> {
+    pub fn read(c: &walker::Cursor, config: &Config) -> Vec<RecordDecl> {
         let mut fields = Vec::new();
         let struct_name = c.name();
         let mut res = Vec::new();
+        let mut boxable = false;
         c.visit_children(|c| {
             match c.kind() {
+                CursorKind::ObjCBoxable => {
+                    boxable = true;
+                }
                 CursorKind::FieldDecl => {
                     let name = c.name();
                     if name.is_empty() {
                         println!("Skipping unnamed field in {}", struct_name);
                         return walker::ChildVisit::Continue;
                     }
-                    let ty = Type::read(&c.ty(), None, false);
+                    let ty = Type::read(&c.ty(), None, false, config);
                     if let Type::Record(ref name, ..) = ty {
                         if name.is_empty() {
                             println!("Skipping field to anon record in {}.{}", struct_name, name);
@@ -955,7 +2268,7 @@ impl RecordDecl {
                         println!("Skipping anon record decl in {}", struct_name);
                         return walker::ChildVisit::Continue;
                     }
-                    res.append(&mut RecordDecl::read(&c));
+                    res.append(&mut RecordDecl::read(&c, config));
                 }
                 _ => ()
             }
@@ -964,8 +2277,14 @@ impl RecordDecl {
         res.push(RecordDecl {
             src: c.location().filename(),
             rustname: struct_name,
+            avail: bind_availability(c, config),
             fields: fields,
             union: c.kind() == CursorKind::UnionDecl,
+            boxable_encoding: if boxable {
+                Some(c.ty().objc_encoding())
+            } else {
+                None
+            },
         });
         res
     }
@@ -991,11 +2310,11 @@ struct TypedefDecl {
 }
 
 impl TypedefDecl {
-    pub fn read(c: &walker::Cursor) -> TypedefDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> TypedefDecl {
         TypedefDecl {
             src: c.location().filename(),
             rustname: c.name(),
-            ty: Type::read(&c.typedef_ty(), None, false),
+            ty: Type::read(&c.typedef_ty(), None, false, config),
         }
     }
     pub fn refs(&self) -> Vec<String> {
@@ -1013,21 +2332,30 @@ struct FunctionDecl {
     args: Vec<(String, Type)>,
     retty: Type,
     variadic: bool,
+    link_name: Option<String>,
 }
 
 impl FunctionDecl {
-    pub fn read(c: &walker::Cursor) -> FunctionDecl {
+    pub fn read(c: &walker::Cursor, config: &Config) -> FunctionDecl {
         let args =
             c.arg_iter().map(|a|
-                (a.name(), Type::read(&a.ty(), None, false))
+                (a.name(), Type::read_param(&a.ty(), config))
             ).collect();
+        let mut link_name = None;
+        c.visit_children(|c| {
+            if c.kind() == CursorKind::AsmLabelAttr {
+                link_name = Some(c.spelling());
+            }
+            walker::ChildVisit::Continue
+        });
         FunctionDecl {
             src: c.location().filename(),
             rustname: c.spelling(),
-            avail: bind_availability(c),
+            avail: bind_availability(c, config),
             args: args,
-            retty: Type::read(&c.result_ty(), None, false),
+            retty: Type::read(&c.result_ty(), None, false, config),
             variadic: c.is_variadic(),
+            link_name: link_name,
         }
     }
     pub fn refs(&self) -> Vec<String> {
@@ -1109,47 +2437,444 @@ impl ItemDecl {
     }
 }
 
+/// Options controlling how bindings are generated. `Config::default()`
+/// produces the same bindings RustKit itself uses.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Scalar typedefs to emit as `#[repr(transparent)]` newtypes instead
+    /// of plain aliases, so they can't be mixed up with the primitive.
+    pub newtype_typedefs: HashSet<String>,
+    /// Hand-written bodies for methods the generator gets wrong, keyed by
+    /// ObjC class (or protocol) name and selector. The generated signature
+    /// is kept and the body is replaced with this source, e.g.
+    /// `"{ unimplemented!() }"`.
+    pub method_overrides: HashMap<(String, String), String>,
+    /// Methods to leave out entirely so they can be written by hand in
+    /// another module. Keyed the same way as `method_overrides`.
+    pub skip_methods: HashSet<(String, String)>,
+    /// Type and function names to generate bindings for. Everything they
+    /// transitively reference is generated too, and the rest is dropped.
+    /// Protocols can be named with or without the `Proto` suffix. When
+    /// empty, everything is generated.
+    pub roots: Vec<String>,
+    /// Maps ObjC names to Rust identifiers, for house naming conventions
+    /// or one-off fixes. Methods are passed their full selector. Renamed
+    /// classes are emitted as aliases of the ObjC name. Frameworks are
+    /// regenerated on every build while this is set, since there's no
+    /// telling whether the function changed.
+    pub rename: Option<fn(&str, NameKind) -> String>,
+    /// Frameworks to generate without a `#[link]` attribute, so they can be
+    /// weak-linked with `-weak_framework` instead.
+    pub weak_frameworks: HashSet<String>,
+    /// Also generate unsafe `<method>_autoreleased` variants of instance
+    /// methods returning autoreleased objects, which skip the retain and
+    /// return a reference that's valid until the autorelease pool drains.
+    pub borrowed_returns: bool,
+    /// Also generate unsafe `super_<method>` variants of every instance
+    /// method, for overrides in subclasses defined from Rust to call the
+    /// superclass' implementation. Designated initializers always get
+    /// one.
+    pub super_calls: bool,
+    /// Path the generated code uses to reach the `objc` runtime module,
+    /// `c_void` and friends, and the framework modules, e.g.
+    /// `"::rustkit"`. Defaults to the crate root.
+    pub root_path: Option<String>,
+    /// Target triple to parse headers for, e.g. `aarch64-apple-darwin`.
+    /// Layouts and `long` widths follow the host when unset.
+    pub target: Option<String>,
+    /// Trust `_Nonnull` on object returns and hand back `Arc<T>` for them.
+    /// Headers aren't always annotated correctly, and a nil return from a
+    /// method marked nonnull ends up as a null `Arc`. Turning this off
+    /// returns `Option<Arc<T>>` for every object, at the cost of an unwrap
+    /// at each call site. On by default.
+    pub assume_nonnull_annotations: bool,
+    /// Headers to parse along with a framework's umbrella header, keyed by
+    /// framework name, for ones the umbrella header doesn't include, e.g.
+    /// `"IOSurface/IOSurfaceObjC.h"` for IOSurface.
+    pub extra_headers: HashMap<String, Vec<String>>,
+    /// How method names are derived from selectors when `rename` doesn't
+    /// handle them.
+    pub method_naming: MethodNaming,
+    /// Classes whose instances can be shared between threads, which makes
+    /// `Arc<T>` of them `Send` and `Sync`. Only the named class is marked,
+    /// not its subclasses, but an `Arc<T>` can still point at an instance
+    /// of any subclass, so a class with a mutable subclass (NSString,
+    /// NSArray and the like) must not be listed. Defaults to Foundation's
+    /// immutable value classes that have none.
+    pub thread_safe_classes: HashSet<String>,
+    /// Oldest OS version the bindings have to run on, as (major, minor)
+    /// on the target's platform. Anything introduced after it is left
+    /// out, the way unavailable APIs are, so using one doesn't compile.
+    /// Everything is bound when unset.
+    pub deployment_target: Option<(u32, u32)>,
+    /// Name property accessors after the property, in snake case, e.g.
+    /// `hidden()` and `set_hidden()` rather than `isHidden()` and
+    /// `setHidden_()`. They still send the selectors the property uses.
+    pub property_accessors: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            newtype_typedefs: HashSet::new(),
+            method_overrides: HashMap::new(),
+            skip_methods: HashSet::new(),
+            roots: Vec::new(),
+            rename: None,
+            weak_frameworks: HashSet::new(),
+            borrowed_returns: false,
+            super_calls: false,
+            root_path: None,
+            target: None,
+            assume_nonnull_annotations: true,
+            extra_headers: [
+                ("IOSurface".to_owned(), vec!["IOSurface/IOSurfaceObjC.h".to_owned()]),
+            ].iter().cloned().collect(),
+            method_naming: MethodNaming::Selector,
+            // From the thread safety summary in Apple's Threading
+            // Programming Guide, leaving out classes with mutable
+            // subclasses.
+            thread_safe_classes: [
+                "NSDate",
+                "NSDecimalNumber",
+                "NSNull",
+                "NSNumber",
+                "NSTimeZone",
+                "NSURL",
+                "NSUUID",
+                "NSValue",
+            ].iter().map(|c| c.to_string()).collect(),
+            deployment_target: None,
+            property_accessors: false,
+        }
+    }
+}
+
+impl Config {
+    // Sets iterate in a different order each run, so they're hashed
+    // sorted. A rename function can't be compared, so bind_framework
+    // doesn't use the cache at all for configs with one.
+    fn cache_hash<H: Hasher>(&self, h: &mut H) {
+        fn sorted<T: Ord, I: IntoIterator<Item = T>>(items: I) -> Vec<T> {
+            let mut items: Vec<T> = items.into_iter().collect();
+            items.sort();
+            items
+        }
+        sorted(&self.newtype_typedefs).hash(h);
+        sorted(&self.method_overrides).hash(h);
+        sorted(&self.skip_methods).hash(h);
+        self.roots.hash(h);
+        sorted(&self.weak_frameworks).hash(h);
+        self.borrowed_returns.hash(h);
+        self.super_calls.hash(h);
+        self.root_path.hash(h);
+        self.target.hash(h);
+        self.assume_nonnull_annotations.hash(h);
+        sorted(&self.extra_headers).hash(h);
+        self.method_naming.hash(h);
+        sorted(&self.thread_safe_classes).hash(h);
+        self.deployment_target.hash(h);
+        self.property_accessors.hash(h);
+    }
+
+    // The platform name availability attributes use for the target.
+    fn platform(&self) -> &'static str {
+        let target = match self.target {
+            Some(ref target) => target,
+            None => return "macos",
+        };
+        if target.ends_with("-macabi") {
+            "maccatalyst"
+        } else if target.contains("-ios") {
+            "ios"
+        } else if target.contains("-tvos") {
+            "tvos"
+        } else if target.contains("-watchos") {
+            "watchos"
+        } else {
+            "macos"
+        }
+    }
+
+    // Rust's simulator triples end in -sim where clang wants -simulator.
+    fn clang_target(&self) -> Option<String> {
+        self.target.as_ref().map(|target| {
+            if target.ends_with("-sim") {
+                format!("{}ulator", target)
+            } else {
+                target.clone()
+            }
+        })
+    }
+
+    // In bytes, for the target headers are parsed for.
+    fn pointer_width(&self) -> usize {
+        match self.target {
+            Some(ref target) => {
+                let arch = target.split('-').next().unwrap_or("");
+                if arch.contains("64") && !arch.ends_with("_32") {
+                    8
+                } else {
+                    4
+                }
+            },
+            None => std::mem::size_of::<usize>(),
+        }
+    }
+
+    fn rust_name(&self, name: &str, kind: NameKind) -> String {
+        if let Some(rename) = self.rename {
+            return rename(name, kind);
+        }
+        match kind {
+            NameKind::Method => match self.method_naming {
+                MethodNaming::Selector => name.replace(":", "_"),
+                MethodNaming::SnakeCase => snake_case_selector(name),
+            },
+            NameKind::Class | NameKind::EnumVariant => name.to_owned(),
+        }
+    }
+}
+
+/// The kind of item a name passed to `Config::rename` belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameKind {
+    Method,
+    Class,
+    EnumVariant,
+}
+
+/// How `Config::rust_name` turns a selector into a method name.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum MethodNaming {
+    /// Colons become underscores, e.g. `initWithObjects_count_`.
+    Selector,
+    /// Each keyword is split into lowercase words, e.g.
+    /// `init_with_objects_count`. Acronyms like `URL` stay whole.
+    SnakeCase,
+}
+
+// Word breaks go before a capital following a lowercase letter or digit,
+// and before the last capital of an acronym followed by a lowercase word,
+// unless that's just a plural "s" as in URLs.
+fn snake_case_selector(sel: &str) -> String {
+    let mut out = String::new();
+    for keyword in sel.split(':').filter(|k| !k.is_empty()) {
+        if !out.is_empty() {
+            out.push('_');
+        }
+        let chars: Vec<char> = keyword.chars().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            if i > 0 && c.is_ascii_uppercase() {
+                let prev = chars[i - 1];
+                let lower_run = chars[i + 1..].iter().take_while(|c| c.is_ascii_lowercase()).count();
+                let plural = lower_run == 1 && chars[i + 1] == 's';
+                if prev.is_ascii_lowercase() || prev.is_ascii_digit() ||
+                   (prev.is_ascii_uppercase() && lower_run > 0 && !plural) {
+                    out.push('_');
+                }
+            }
+            out.push(c.to_ascii_lowercase());
+        }
+    }
+    out
+}
+
+/// Where Xcode keeps the SDK for a target triple, e.g. the iPhoneOS SDK
+/// for `aarch64-apple-ios` and the simulator's for `aarch64-apple-ios-sim`
+/// or `x86_64-apple-ios`, and likewise for tvOS and watchOS. Mac Catalyst
+/// builds against the macOS SDK.
+pub fn default_sdk_path(target: Option<&str>) -> PathBuf {
+    let target = target.unwrap_or("");
+    let arch = target.split('-').next().unwrap_or("");
+    let simulator = target.ends_with("-sim") || arch == "x86_64" || arch == "i386";
+    let platform = if target.ends_with("-macabi") {
+        "MacOSX"
+    } else if target.contains("-ios") {
+        if simulator { "iPhoneSimulator" } else { "iPhoneOS" }
+    } else if target.contains("-tvos") {
+        if simulator { "AppleTVSimulator" } else { "AppleTVOS" }
+    } else if target.contains("-watchos") {
+        if simulator { "WatchSimulator" } else { "WatchOS" }
+    } else {
+        "MacOSX"
+    };
+    PathBuf::from(format!(
+        "/Applications/Xcode.app/Contents/Developer/Platforms/{0}.platform/Developer/SDKs/{0}.sdk",
+        platform))
+}
+
 pub fn bind_framework(
     sdk_path: &Path,
     framework_name: &str,
     out_dir: &Path,
+    config: &Config,
 ) -> HashSet<String> {
+    let (framework_path, args) = framework_args(sdk_path, framework_name, config);
+    let cache_path = out_dir.join(format!("{}.cache", framework_name));
+    let key = cache_key(sdk_path, &framework_path, config);
+    let generated = out_dir.join(format!("{}.rs", framework_name)).exists() ||
+        out_dir.join(framework_name).join("mod.rs").exists();
+    // A rename function can't be hashed, and could have changed since the
+    // cache was written, so it's only trusted without one.
+    if generated && config.rename.is_none() {
+        if let Some(deps) = read_cache(&cache_path, key) {
+            return deps;
+        }
+    }
+
+    if !clang::is_loaded() {
+        clang::load().unwrap();
+    }
+
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let idx = walker::Index::new().unwrap();
+    let tu = idx.parse_tu(&args).unwrap();
+    let mut out_path = out_dir.to_owned();
+    out_path.push(&format!("{}.rs", framework_name));
+    let deps = bind_tu(&tu, &framework_path, Some(framework_name), &out_path, config);
+    write_cache(&cache_path, key, &deps);
+    deps
+}
+
+/* A framework's generated code is kept between build script runs along
+ * with a <framework>.cache file recording what it was generated from and
+ * the frameworks it depends on. The build script is relinked whenever the
+ * generator changes, so its mtime is part of the key too. Bump
+ * CACHE_VERSION if the cache file's format changes.
+ */
+const CACHE_VERSION: u32 = 1;
+
+// Covers the headers (including sub-frameworks'), the SDK they came from,
+// the generator and the config.
+fn cache_key(sdk_path: &Path, framework_path: &Path, config: &Config) -> u64 {
+    fn max_mtime(path: &Path, newest: &mut std::time::SystemTime) {
+        let entries = match std::fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let meta = match std::fs::metadata(entry.path()) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            if meta.is_dir() {
+                max_mtime(&entry.path(), newest);
+            } else if let Ok(mtime) = meta.modified() {
+                if mtime > *newest {
+                    *newest = mtime;
+                }
+            }
+        }
+    }
+    let mut newest = std::time::UNIX_EPOCH;
+    max_mtime(framework_path, &mut newest);
+    max_mtime(&framework_path.with_file_name("Frameworks"), &mut newest);
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    CACHE_VERSION.hash(&mut hasher);
+    sdk_path.hash(&mut hasher);
+    newest.hash(&mut hasher);
+    std::env::current_exe().and_then(|exe| exe.metadata()).
+        and_then(|meta| meta.modified()).ok().hash(&mut hasher);
+    config.cache_hash(&mut hasher);
+    hasher.finish()
+}
+
+fn read_cache(cache_path: &Path, key: u64) -> Option<HashSet<String>> {
+    let file = File::open(cache_path).ok()?;
+    let mut lines = BufReader::new(file).lines();
+    if lines.next()?.ok()? != format!("key {:016x}", key) {
+        return None;
+    }
+    let mut deps = HashSet::new();
+    for line in lines {
+        let line = line.ok()?;
+        deps.insert(line.strip_prefix("dep ")?.to_owned());
+    }
+    Some(deps)
+}
+
+fn write_cache(cache_path: &Path, key: u64, deps: &HashSet<String>) {
+    let mut f = File::create(cache_path).unwrap();
+    writeln!(f, "key {:016x}", key).unwrap();
+    let mut deps: Vec<&String> = deps.iter().collect();
+    deps.sort();
+    for d in deps {
+        writeln!(f, "dep {}", d).unwrap();
+    }
+}
+
+/// Generates a framework the way `bind_framework` does, but hands back
+/// the code instead of writing it out. Sub-frameworks become inline
+/// modules. Nothing is formatted, and frameworks it depends on aren't
+/// generated.
+pub fn bind_framework_to_tokens(
+    sdk_path: &Path,
+    framework_name: &str,
+    config: &Config,
+) -> proc_macro2::TokenStream {
     if !clang::is_loaded() {
         clang::load().unwrap();
     }
 
+    let (framework_path, args) = framework_args(sdk_path, framework_name, config);
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let idx = walker::Index::new().unwrap();
+    let tu = idx.parse_tu(&args).unwrap();
+    let (decls, declnames) = read_decls(&tu, &framework_path, config);
+    let mods = subframeworks(&framework_path);
+    let mut deps = HashSet::new();
+    let mut ast = gen_ast(&decls, &declnames, &framework_path, &[], Some(framework_name), false, &mut deps, config);
+    for m in mods {
+        let subbase_path = subframework_path(&framework_path, &m);
+        let items = gen_ast(&decls, &declnames, &subbase_path, &[], None, false, &mut deps, config).items;
+        let m = Ident::new(&m, Span::call_site());
+        ast.items.push(parse_quote!{
+            pub mod #m {
+                #(#items)*
+            }
+        });
+    }
+    ast.into_token_stream()
+}
+
+// The framework's headers directory, and the clang arguments to parse its
+// umbrella header with.
+fn framework_args(sdk_path: &Path, framework_name: &str, config: &Config) -> (PathBuf, Vec<String>) {
     let mut framework_path = sdk_path.to_owned();
     framework_path.push("System/Library/Frameworks");
     framework_path.push(&format!("{}.framework/Headers", framework_name));
     let mut include_path = framework_path.clone();
     include_path.push(&format!("{}.h", framework_name));
     let sdk_path_str = sdk_path.to_str().unwrap();
-    let idx = walker::Index::new().unwrap();
-    let framework_include = format!("-F{}/System/Library/Frameworks", sdk_path_str);
-    let system_include_path = format!("-I{}/usr/include", sdk_path_str);
-    let mut args = vec![
-        "-ObjC",
-        "-fobjc-arc",
-        "-fno-objc-exceptions",
-        "-fobjc-abi-version=2",
-        &framework_include,
-        &system_include_path,
-        include_path.to_str().unwrap(),
+    let mut args: Vec<String> = vec![
+        "-ObjC".to_owned(),
+        "-fobjc-arc".to_owned(),
+        "-fno-objc-exceptions".to_owned(),
+        "-fobjc-abi-version=2".to_owned(),
+        format!("-F{}/System/Library/Frameworks", sdk_path_str),
+        format!("-I{}/usr/include", sdk_path_str),
+        include_path.to_str().unwrap().to_owned(),
     ];
-    if framework_name == "IOSurface" {
-        args.push("-include");
-        args.push("IOSurface/IOSurfaceObjC.h");
+    if let Some(target) = config.clang_target() {
+        args.push("-target".to_owned());
+        args.push(target);
     }
-    let tu = idx.parse_tu(&args).unwrap();
-    let mut out_path = out_dir.to_owned();
-    out_path.push(&format!("{}.rs", framework_name));
-    bind_tu(&tu, &framework_path, Some(framework_name), &out_path)
+    if let Some(headers) = config.extra_headers.get(framework_name) {
+        for header in headers {
+            args.push("-include".to_owned());
+            args.push(header.clone());
+        }
+    }
+    (framework_path, args)
 }
 
 pub fn bind_file(
     sdk_path: &Path,
     header_path: &Path,
     out_dir: &Path,
+    config: &Config,
 ) {
     if !clang::is_loaded() {
         clang::load().unwrap();
@@ -1159,7 +2884,7 @@ pub fn bind_file(
     let idx = walker::Index::new().unwrap();
     let framework_include = format!("-F{}/System/Library/Frameworks", sdk_path_str);
     let system_include_path = format!("-I{}/usr/include", sdk_path_str);
-    let args = vec![
+    let mut args = vec![
         "-ObjC",
         "-fobjc-arc",
         "-fno-objc-exceptions",
@@ -1168,10 +2893,179 @@ pub fn bind_file(
         &system_include_path,
         header_path.to_str().unwrap(),
     ];
+    let target = config.clang_target();
+    if let Some(ref target) = target {
+        args.push("-target");
+        args.push(target);
+    }
     let tu = idx.parse_tu(&args).unwrap();
     let mut out_path = out_dir.to_owned();
     out_path.push(&format!("{}.rs", header_path.file_stem().unwrap().to_str().unwrap()));
-    bind_tu(&tu, &header_path, None, &out_path);
+    bind_tu(&tu, &header_path, None, &out_path, config);
+}
+
+/// Generates a set of frameworks and system headers into one directory,
+/// along with every framework they depend on, and writes a `top.rs` there
+/// declaring them all. Including it at the crate root with
+/// `include!(concat!(env!("OUT_DIR"), "/top.rs"));` gives a module per
+/// framework, e.g. `Foundation`, plus the headers' items at the root.
+///
+/// Each framework's items are gated on an `RK_<framework>` cargo feature,
+/// so the crate needs one declared for every framework `generate` returns.
+/// They're also written to `features.toml`, one line per feature that
+/// enables the features of the frameworks it references, ready to be
+/// pasted into the crate's `[features]` table.
+pub struct Binder {
+    sdk_path: PathBuf,
+    out_dir: PathBuf,
+    frameworks: Vec<String>,
+    headers: Vec<String>,
+    threads: usize,
+}
+
+impl Binder {
+    pub fn new(sdk_path: &Path, out_dir: &Path) -> Binder {
+        Binder {
+            sdk_path: sdk_path.to_owned(),
+            out_dir: out_dir.to_owned(),
+            frameworks: Vec::new(),
+            headers: Vec::new(),
+            threads: 0,
+        }
+    }
+
+    /// Sets how many frameworks are bound at once. The default, 0, is one
+    /// per core.
+    pub fn threads(mut self, n: usize) -> Binder {
+        self.threads = n;
+        self
+    }
+
+    /// Adds a framework by name, e.g. `"AppKit"`.
+    pub fn framework(mut self, name: &str) -> Binder {
+        self.frameworks.push(name.to_owned());
+        self
+    }
+
+    /// Adds a header by its path under the SDK's `usr/include`, e.g.
+    /// `"objc/NSObject.h"`, or by its absolute path for headers outside
+    /// the SDK.
+    pub fn header(mut self, path: &str) -> Binder {
+        self.headers.push(path.to_owned());
+        self
+    }
+
+    /// Generates everything, returning the frameworks that were generated
+    /// including dependencies, sorted by name.
+    pub fn generate(&self, config: &Config) -> Vec<String> {
+        let mut top = File::create(self.out_dir.join("top.rs")).unwrap();
+        for header in &self.headers {
+            let header_path = self.sdk_path.join("usr/include").join(header);
+            bind_file(&self.sdk_path, &header_path, &self.out_dir, config);
+            writeln!(top, "include!(concat!(env!(\"OUT_DIR\"), \"/{}.rs\"));",
+                     header_path.file_stem().unwrap().to_str().unwrap()).unwrap();
+        }
+        let done = self.bind_frameworks(config);
+        let mut features = File::create(self.out_dir.join("features.toml")).unwrap();
+        for (f, deps) in &done {
+            writeln!(top, "pub mod {};", f).unwrap();
+            let deps: Vec<String> =
+                deps.iter().map(|d| format!("\"RK_{}\"", d)).collect();
+            writeln!(features, "RK_{} = [{}]", f, deps.join(", ")).unwrap();
+        }
+        done.keys().cloned().collect()
+    }
+
+    // Binds the frameworks and everything they depend on, on a thread per
+    // core. Each framework's parse is independent, and clang is loaded
+    // and indexes are created per thread, so the only shared state is the
+    // queue. Dependencies are queued as soon as a framework finishes. If a
+    // worker panics, its guard still takes its framework out of flight so
+    // the rest stop waiting on it, and the panic resurfaces from the scope.
+    fn bind_frameworks(&self, config: &Config) -> BTreeMap<String, Vec<String>> {
+        struct Queue {
+            pending: Vec<String>,
+            seen: HashSet<String>,
+            in_flight: usize,
+            done: BTreeMap<String, Vec<String>>,
+        }
+        struct InFlight<'a> {
+            queue: &'a Mutex<Queue>,
+            cond: &'a Condvar,
+        }
+        impl<'a> Drop for InFlight<'a> {
+            fn drop(&mut self) {
+                let mut q = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+                q.in_flight -= 1;
+                self.cond.notify_all();
+            }
+        }
+        let start = Instant::now();
+        let queue = Mutex::new(Queue {
+            pending: self.frameworks.clone(),
+            seen: self.frameworks.iter().cloned().collect(),
+            in_flight: 0,
+            done: BTreeMap::new(),
+        });
+        let cond = Condvar::new();
+        let workers = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| loop {
+                    let (f, _in_flight) = {
+                        let mut q = queue.lock().unwrap();
+                        loop {
+                            if let Some(f) = q.pending.pop() {
+                                q.in_flight += 1;
+                                break (f, InFlight { queue: &queue, cond: &cond });
+                            }
+                            if q.in_flight == 0 {
+                                return;
+                            }
+                            q = cond.wait(q).unwrap();
+                        }
+                    };
+                    let newdeps = bind_framework(&self.sdk_path, &f, &self.out_dir, config);
+                    let mut q = queue.lock().unwrap();
+                    for d in &newdeps {
+                        if q.seen.insert(d.clone()) {
+                            q.pending.push(d.clone());
+                        }
+                    }
+                    let mut newdeps: Vec<String> =
+                        newdeps.into_iter().filter(|d| *d != f).collect();
+                    newdeps.sort();
+                    q.done.insert(f, newdeps);
+                });
+            }
+        });
+        let done = queue.into_inner().unwrap().done;
+        println!("Bound {} frameworks in {:.1?} on {} threads",
+                 done.len(), start.elapsed(), workers);
+        done
+    }
+}
+
+fn reachable_decls(decls: &HashMap<String, ItemDecl>, roots: &[String]) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut pending: Vec<String> = Vec::new();
+    for r in roots {
+        pending.push(r.clone());
+        pending.push(format!("{}Proto", r));
+    }
+    while let Some(name) = pending.pop() {
+        if reachable.contains(&name) {
+            continue;
+        }
+        if let Some(d) = decls.get(&name) {
+            pending.extend(d.refs());
+            reachable.insert(name);
+        }
+    }
+    reachable
 }
 
 pub fn bind_tu(
@@ -1179,7 +3073,57 @@ pub fn bind_tu(
     base_path: &Path,
     framework_name: Option<&str>,
     out_path: &Path,
+    config: &Config,
 ) -> HashSet<String> {
+    let (decls, declnames) = read_decls(tu, base_path, config);
+    let mods = subframeworks(base_path);
+
+    let mut deps = HashSet::new();
+    if mods.is_empty() {
+        gen_file(&decls, &declnames, base_path, &mods, framework_name, framework_name.is_none(), out_path, &mut deps, config);
+        return deps;
+    }
+
+    let mut out_path = out_path.to_owned();
+    out_path.pop();
+    out_path.push(framework_name.unwrap());
+    let _ = std::fs::create_dir(&out_path);
+    {
+        let mut subout_path = out_path.clone();
+        subout_path.push("mod.rs");
+        gen_file(&decls, &declnames, base_path, &mods, framework_name, false, &subout_path, &mut deps, config);
+    }
+    for m in mods {
+        let subbase_path = subframework_path(base_path, &m);
+        let mut subout_path = out_path.clone();
+        subout_path.push(&format!("{}.rs", m));
+        gen_file(&decls, &declnames, &subbase_path, &[], None, false, &subout_path, &mut deps, config);
+    }
+    deps
+}
+
+// Names of the frameworks nested in the Frameworks directory next to a
+// framework's headers, like CoreServices' LaunchServices.
+fn subframeworks(base_path: &Path) -> Vec<String> {
+    let mut subframeworks_path = base_path.to_owned();
+    subframeworks_path.pop();
+    subframeworks_path.push("Frameworks");
+    std::fs::read_dir(&subframeworks_path).map(|rd| rd.map(|e| e.unwrap().path().file_stem().unwrap().to_str().unwrap().to_owned()).collect::<Vec<_>>()).unwrap_or(Vec::new())
+}
+
+fn subframework_path(base_path: &Path, name: &str) -> PathBuf {
+    let mut subbase_path = base_path.to_owned();
+    subbase_path.pop();
+    subbase_path.push("Frameworks");
+    subbase_path.push(&format!("{}.framework/Headers", name));
+    subbase_path
+}
+
+fn read_decls(
+    tu: &walker::TranslationUnit,
+    base_path: &Path,
+    config: &Config,
+) -> (HashMap<String, ItemDecl>, Vec<String>) {
     let mut decls = HashMap::new();
     let mut declnames = Vec::new();
     let mut anonnames = Vec::new();
@@ -1187,13 +3131,18 @@ pub fn bind_tu(
         if let walker::Availability::NotAvailable(_) = c.availability() {
             return walker::ChildVisit::Continue;
         }
+        if introduced_after_target(&c, config).is_some() {
+            return walker::ChildVisit::Continue;
+        }
         match c.kind() {
             CursorKind::ObjCCategoryDecl => {
-                let class = ClassDecl::read(&c);
+                let class = ClassDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", class);
                     cursor_dump(&c, None);
                 }
+                // Class extensions are categories without a name, and get
+                // folded in the same way through their class reference.
                 let mut classname = String::new();
                 c.visit_children(|c| {
                     match c.kind() {
@@ -1206,13 +3155,13 @@ pub fn bind_tu(
                 });
                 decls.entry(classname).and_modify(|e|
                     if let ItemDecl::Class(ref mut class) = e {
-                        class.read_category(&c);
+                        class.read_category(&c, config);
                     }
                 );
             }
             CursorKind::ObjCInterfaceDecl => {
                 let name = c.name();
-                let class = ClassDecl::read(&c);
+                let class = ClassDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", class);
                     cursor_dump(&c, None);
@@ -1226,7 +3175,7 @@ pub fn bind_tu(
             CursorKind::ObjCProtocolDecl => {
                 let mut name = c.name();
                 name.push_str("Proto");
-                let proto = ClassDecl::read(&c);
+                let proto = ClassDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", proto);
                     cursor_dump(&c, None);
@@ -1247,7 +3196,7 @@ pub fn bind_tu(
                 if !c.is_definition() {
                     return walker::ChildVisit::Continue;
                 }
-                let decl = EnumDecl::read(&c);
+                let decl = EnumDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", decl);
                     cursor_dump(&c, None);
@@ -1268,7 +3217,7 @@ pub fn bind_tu(
                 if c.is_definition() && decls.contains_key(&name) {
                     return walker::ChildVisit::Continue;
                 }
-                let decl = RecordDecl::read(&c);
+                let decl = RecordDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     for d in &decl {
                         println!("{:#?}", d);
@@ -1304,7 +3253,7 @@ pub fn bind_tu(
                         }
                         if nty.kind() == TypeKind::Record {
                             let decl = decls.entry(decl_name.clone()).or_insert_with(|| {
-                                let mut r = RecordDecl::read(&decl).pop().unwrap();
+                                let mut r = RecordDecl::read(&decl, config).pop().unwrap();
                                 r.rustname = decl_name.clone();
                                 anonnames.push((ty.canonical().decl().location(), decl_name.clone()));
                                 declnames.push(decl_name);
@@ -1338,7 +3287,7 @@ pub fn bind_tu(
                                 }
                                 decls.insert(c.name(), i);
                             } else if decl.name().is_empty() {
-                                let mut e = EnumDecl::read(&decl);
+                                let mut e = EnumDecl::read(&decl, config);
                                 e.rustname = decl_name.clone();
                                 declnames.push(decl_name.clone());
                                 decls.insert(decl_name, ItemDecl::Enum(e));
@@ -1359,7 +3308,7 @@ pub fn bind_tu(
                             let loc = cdecl.location();
                             let realname = anonnames.iter().find(|(l, _)| *l == loc);
                             if let Some((_, name)) = realname {
-                                let mut decl = TypedefDecl::read(&c);
+                                let mut decl = TypedefDecl::read(&c, config);
                                 if let Type::Pointer(ref mut ty, ..) = decl.ty {
                                     if let Type::Record(_, u) = **ty {
                                         **ty = Type::Record(name.clone(), u);
@@ -1377,7 +3326,7 @@ pub fn bind_tu(
                 if !standard_typedef {
                     return walker::ChildVisit::Continue;
                 }
-                let decl = TypedefDecl::read(&c);
+                let decl = TypedefDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", decl);
                     cursor_dump(&c, None);
@@ -1390,8 +3339,28 @@ pub fn bind_tu(
                     declnames.push(name);
                 }
             }
+            CursorKind::UnexposedDecl => {
+                // libclang doesn't expose @compatibility_alias, so pick it
+                // out of the tokens and bind it as a typedef.
+                let tokens = c.tokens();
+                if let Some(i) = tokens.iter().position(|t| t == "compatibility_alias") {
+                    if let (Some(alias), Some(class)) = (tokens.get(i + 1), tokens.get(i + 2)) {
+                        let decl = TypedefDecl {
+                            src: c.location().filename(),
+                            rustname: alias.clone(),
+                            ty: Type::Class(class.clone(), Vec::new(), Vec::new()),
+                        };
+                        let old = decls.insert(alias.clone(), ItemDecl::Typedef(decl));
+                        if old.is_some() {
+                            println!("??? alias {} already defined", alias);
+                        } else {
+                            declnames.push(alias.clone());
+                        }
+                    }
+                }
+            }
             CursorKind::FunctionDecl => {
-                let decl = FunctionDecl::read(&c);
+                let decl = FunctionDecl::read(&c, config);
                 if c.location().filename().starts_with(base_path) {
                     println!("{:#?}", decl);
                     cursor_dump(&c, None);
@@ -1406,37 +3375,22 @@ pub fn bind_tu(
             }
             _ => (),
         };
-        walker::ChildVisit::Continue
-    });
-
-    let mut subframeworks_path = base_path.to_owned();
-    subframeworks_path.pop();
-    subframeworks_path.push("Frameworks");
-    let mods = std::fs::read_dir(&subframeworks_path).map(|rd| rd.map(|e| e.unwrap().path().file_stem().unwrap().to_str().unwrap().to_owned()).collect::<Vec<_>>()).unwrap_or(Vec::new());
+        walker::ChildVisit::Continue
+    });
 
-    let mut deps = HashSet::new();
-    if mods.is_empty() {
-        gen_file(&decls, &declnames, base_path, &mods, framework_name, framework_name.is_none(), out_path, &mut deps);
-        return deps;
+    for d in decls.values_mut() {
+        match d {
+            ItemDecl::Class(c) | ItemDecl::Proto(c) => c.resolve_name_collisions(),
+            _ => {}
+        }
     }
 
-    let mut out_path = out_path.to_owned();
-    out_path.pop();
-    out_path.push(framework_name.unwrap());
-    let _ = std::fs::create_dir(&out_path);
-    {
-        let mut subout_path = out_path.clone();
-        subout_path.push("mod.rs");
-        gen_file(&decls, &declnames, base_path, &mods, framework_name, false, &subout_path, &mut deps);
-    }
-    for m in mods {
-        let mut subbase_path = subframeworks_path.to_owned();
-        subbase_path.push(&format!("{}.framework/Headers", m));
-        let mut subout_path = out_path.clone();
-        subout_path.push(&format!("{}.rs", m));
-        gen_file(&decls, &declnames, &subbase_path, &[], None, false, &subout_path, &mut deps);
+    if !config.roots.is_empty() {
+        let reachable = reachable_decls(&decls, &config.roots);
+        decls.retain(|k, _| reachable.contains(k));
+        declnames.retain(|k| reachable.contains(k));
     }
-    deps
+    (decls, declnames)
 }
 
 fn gen_file(
@@ -1448,7 +3402,25 @@ fn gen_file(
     file_mode: bool,
     out_path: &Path,
     deps: &mut HashSet<String>,
+    config: &Config,
 ) {
+    let ast = gen_ast(decls, declnames, base_path, mods, framework_name, file_mode, deps, config);
+    let mut f = File::create(out_path).unwrap();
+    f.write_fmt(format_args!("{}", ast.into_token_stream())).unwrap();
+    f.flush().unwrap();
+    std::process::Command::new("rustfmt").arg(out_path).status().unwrap();
+}
+
+fn gen_ast(
+    decls: &HashMap<String, ItemDecl>,
+    declnames: &[String],
+    base_path: &Path,
+    mods: &[String],
+    framework_name: Option<&str>,
+    file_mode: bool,
+    deps: &mut HashSet<String>,
+    config: &Config,
+) -> syn::File {
     let mut selectors = HashSet::new();
     for d in decls.values() {
         match d {
@@ -1487,6 +3459,16 @@ fn gen_file(
             Some(parse_quote!(#[cfg(all(#(feature = #frameworks),*))]))
         }
     }
+    // The framework_name an item's own module was generated with, which
+    // is None for subframeworks.
+    fn gen_framework_name(d: &ItemDecl) -> Option<String> {
+        let names = d.framework_name();
+        if names.len() == 1 {
+            Some(names[0].clone())
+        } else {
+            None
+        }
+    }
     let mut uses = HashSet::new();
     for d in decls.values() {
         if !d.src().starts_with(base_path) {
@@ -1495,6 +3477,21 @@ fn gen_file(
         for r in d.refs() {
             uses.insert(r);
         }
+        // Conformances inherited from superclasses (most importantly
+        // NSObjectProto) are implemented here too, so their traits need
+        // to be in scope even if this file never names them.
+        if let ItemDecl::Class(c) = d {
+            for p in c.all_protocols(decls) {
+                // Required methods are implemented here too, so the
+                // types they use need to be in scope as well.
+                if let Some(ItemDecl::Proto(proto)) = decls.get(&format!("{}Proto", p)) {
+                    for m in proto.imethods.values().filter(|m| !m.optional) {
+                        uses.extend(m.refs());
+                    }
+                }
+                uses.insert(format!("{}Proto", p));
+            }
+        }
     }
     let mut ast = syn::File {
         shebang: None,
@@ -1502,9 +3499,18 @@ fn gen_file(
         items: Vec::new(),
     };
 
+    // Where the objc runtime module, the shared C types and the framework
+    // modules live, so the bindings can be included anywhere.
+    let root = match config.root_path {
+        Some(ref p) => {
+            let p: syn::Path = syn::parse_str(p).unwrap();
+            quote!(#p::)
+        }
+        None => quote!(::),
+    };
     ast.items.push(parse_quote!{
         #[allow(unused_imports)]
-        use objc::*;
+        use #root objc::*;
     });
     if !file_mode {
         ast.items.push(parse_quote!{
@@ -1517,7 +3523,19 @@ fn gen_file(
         });
         ast.items.push(parse_quote!{
             #[allow(unused_imports)]
-            use c_void;
+            use #root c_void;
+        });
+        ast.items.push(parse_quote!{
+            #[allow(unused_imports)]
+            use #root Complex;
+        });
+        ast.items.push(parse_quote!{
+            #[allow(unused_imports)]
+            use #root LongDouble;
+        });
+        ast.items.push(parse_quote!{
+            #[allow(unused_imports)]
+            use #root CGFloat;
         });
     }
     ast.items.extend(uses.iter().filter_map(|n| {
@@ -1537,13 +3555,13 @@ fn gen_file(
                         deps.insert(comp.to_owned());
                     }
                     Some(parse_quote!{
-                        use #path;
+                        use #root #path;
                     })
                 }
             }
             None => {
                 if n == "NSString" {
-                    Some(parse_quote!{ use Foundation::NSString; })
+                    Some(parse_quote!{ use #root Foundation::NSString; })
                 } else {
                     None
                 }
@@ -1598,23 +3616,55 @@ fn gen_file(
                 }).collect();
                 let enum_name = Ident::new(&e.rustname, Span::call_site());
                 let repr_type = e.ty.rust_ty(false);
+                let deprecated = deprecated_attr(&e.avail);
                 if e.flagenum {
                     ast.items.push(parse_quote!{
                         bitflags! {
+                            #deprecated
                             #[repr(C)]
                             pub struct #enum_name: #repr_type {
                                 #(const #variants;)*
                             }
                         }
                     });
-                } else {
+                } else if e.exhaustive {
                     ast.items.push(parse_quote!{
+                        #deprecated
                         #[repr(#repr_type)]
                         #[derive(Copy, Clone)]
                         pub enum #enum_name {
                             #(#variants),*
                         }
                     });
+                } else {
+                    // Values outside the known variants would be undefined
+                    // behavior in a Rust enum, so open enums are newtypes
+                    // around their integer with a constant per variant.
+                    let consts: Vec<syn::ImplItem> = e.variants.iter().map(|(n, v, neg)| {
+                        let var_name = Ident::new(&n, Span::call_site());
+                        let var_val =
+                            syn::LitInt::new(*v, syn::IntSuffix::None, Span::call_site());
+                        let var_val: syn::Expr = if *neg {
+                            parse_quote!{ -#var_val }
+                        } else {
+                            parse_quote!{ #var_val }
+                        };
+                        parse_quote!{
+                            #[allow(non_upper_case_globals)]
+                            pub const #var_name: #enum_name = #enum_name(#var_val);
+                        }
+                    }).collect();
+                    ast.items.push(parse_quote!{
+                        #deprecated
+                        #[repr(transparent)]
+                        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+                        pub struct #enum_name(pub #repr_type);
+                    });
+                    ast.items.push(parse_quote!{
+                        impl #enum_name {
+                            #(#consts)*
+                        }
+                    });
                 }
             }
             ItemDecl::Record(s) => {
@@ -1632,9 +3682,17 @@ fn gen_file(
                 let field_ty: Vec<syn::Type> = s.fields.iter().map(|(_, t)| {
                     t.raw_ty()
                 }).collect();
+                let deprecated = deprecated_attr(&s.avail);
+                // Nothing can pass these by value, so they're only bound
+                // opaquely, for pointers to them.
+                // A vector field's alignment would be wrong too.
+                let opaque = s.fields.iter().any(|(_, t)| {
+                    t.holds(decls, &Type::is_long_double) || t.holds(decls, &Type::is_vector)
+                });
 
-                if s.fields.is_empty() {
+                if s.fields.is_empty() || opaque {
                     ast.items.push(parse_quote!{
+                        #deprecated
                         #[repr(C)]
                         pub struct #struct_name {
                             opaque: u32,
@@ -1642,6 +3700,7 @@ fn gen_file(
                     });
                 } else if s.union {
                     ast.items.push(parse_quote!{
+                        #deprecated
                         #[repr(C)]
                         #[derive(Copy, Clone)]
                         pub union #struct_name {
@@ -1650,6 +3709,7 @@ fn gen_file(
                     });
                 } else {
                     ast.items.push(parse_quote!{
+                        #deprecated
                         #[repr(C)]
                         #[derive(Copy, Clone)]
                         pub struct #struct_name {
@@ -1657,16 +3717,99 @@ fn gen_file(
                         }
                     });
                 }
+
+                // objc_boxable structs can go in and out of NSValues.
+                if let Some(encoding) = s.boxable_encoding.as_ref().filter(|_| !opaque) {
+                    let encoding = proc_macro2::Literal::byte_string(format!("{}\0", encoding).as_bytes());
+                    ast.items.push(parse_quote!{
+                        #[cfg(feature = "RK_Foundation")]
+                        impl #struct_name {
+                            pub fn to_value(&self) -> Arc<#root Foundation::NSValue> {
+                                unsafe {
+                                    let send:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef,
+                                            *const #struct_name,
+                                            *const u8) -> *mut #root Foundation::NSValue =
+                                        std::mem::transmute(objc_msgSend as *const u8);
+                                    let _ret = send(
+                                        <#root Foundation::NSValue as ObjCClass>::classref().0 as *const Object as *mut _,
+                                        sel!("valueWithBytes:objCType:"),
+                                        self,
+                                        #encoding.as_ptr()
+                                    );
+                                    retain_autoreleased!(_ret as *mut _);
+                                    Arc::new_unchecked(_ret)
+                                }
+                            }
+
+                            /// Panics if the value doesn't hold this type.
+                            pub fn from_value(value: &#root Foundation::NSValue) -> #struct_name {
+                                unsafe {
+                                    let objc_type:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef) -> *const i8 =
+                                        std::mem::transmute(objc_msgSend as *const u8);
+                                    let ty = objc_type(
+                                        value as *const _ as *mut _,
+                                        sel!("objCType"));
+                                    assert!(std::ffi::CStr::from_ptr(ty).to_bytes_with_nul() == &#encoding[..],
+                                            "NSValue doesn't hold a {}", stringify!(#struct_name));
+                                    let mut ret: #struct_name = std::mem::zeroed();
+                                    let get_value:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef,
+                                            *mut #struct_name) =
+                                        std::mem::transmute(objc_msgSend as *const u8);
+                                    get_value(
+                                        value as *const _ as *mut _,
+                                        sel!("getValue:"),
+                                        &mut ret);
+                                    ret
+                                }
+                            }
+                        }
+                    });
+                }
             }
             ItemDecl::Typedef(t) => {
                 if !t.src.starts_with(base_path) || t.ty.is_va_list() {
                     continue;
                 }
+                // Defined once at the root for every target instead.
+                if t.rustname == "CGFloat" && !config.newtype_typedefs.contains(&t.rustname) {
+                    continue;
+                }
                 let name = Ident::new(&t.rustname, Span::call_site());
                 let ty = t.ty.raw_ty();
-                ast.items.push(parse_quote!{
-                    pub type #name = #ty;
-                });
+                if config.newtype_typedefs.contains(&t.rustname) && t.ty.is_scalar() {
+                    ast.items.push(parse_quote!{
+                        #[repr(transparent)]
+                        #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+                        pub struct #name(pub #ty);
+                    });
+                    ast.items.push(parse_quote!{
+                        impl From<#ty> for #name {
+                            fn from(v: #ty) -> #name {
+                                #name(v)
+                            }
+                        }
+                    });
+                    ast.items.push(parse_quote!{
+                        impl From<#name> for #ty {
+                            fn from(v: #name) -> #ty {
+                                v.0
+                            }
+                        }
+                    });
+                } else {
+                    ast.items.push(parse_quote!{
+                        pub type #name = #ty;
+                    });
+                }
             }
             ItemDecl::Class(c) => {
                 if !c.src.starts_with(base_path) {
@@ -1700,39 +3843,291 @@ fn gen_file(
                         isa: *const Class,
                     }
                 });
-                let instance_size =
-                    syn::LitInt::new(c.size,
-                                     syn::IntSuffix::None, Span::call_site());
+                // Anything typed id takes an &Object, which every
+                // instance can stand in for.
+                ast.items.push(parse_quote!{
+                    impl AsRef<Object> for #name {
+                        fn as_ref(&self) -> &Object {
+                            unsafe { &*(self as *const Self as *const Object) }
+                        }
+                    }
+                });
+                if c.is_exception(decls) {
+                    ast.items.push(parse_quote!{
+                        impl ObjCException for #name {}
+                    });
+                }
+                if config.thread_safe_classes.contains(k) {
+                    ast.items.push(parse_quote!{
+                        unsafe impl ThreadSafe for #name {}
+                    });
+                }
+                let alias = config.rust_name(&c.rustname, NameKind::Class);
+                if alias != c.rustname {
+                    let alias = Ident::new(&alias, Span::call_site());
+                    ast.items.push(parse_quote!{
+                        pub type #alias = #name;
+                    });
+                }
                 let start: syn::Expr = if c.superclass.is_empty() {
                     parse_quote!(0)
                 } else {
                     let superclass = Ident::new(&c.superclass, Span::call_site());
                     parse_quote!(<#superclass as ObjCClass>::SIZE)
                 };
+                // Without a layout, the best we can say is that the class
+                // is at least as big as its superclass.
+                let (instance_size, complete): (syn::Expr, bool) = match c.size {
+                    Some(size) => {
+                        let size = syn::LitInt::new(size, syn::IntSuffix::None, Span::call_site());
+                        (parse_quote!(#size), true)
+                    }
+                    None => (start.clone(), false),
+                };
                 ast.items.push(parse_quote!{
                     impl ObjCClass for #name {
                         const START: usize = #start;
                         const SIZE: usize = #instance_size;
+                        const COMPLETE: bool = #complete;
                         fn classref() -> ClassRef {
                             #classrefname
                         }
                     }
                 });
-                for p in &c.protocols {
+                // Offsets are as laid out in the SDK. The non-fragile ABI
+                // slides ivars at runtime if a superclass grows, so
+                // subclasses should look them up with ivar_getOffset when
+                // that matters.
+                if !c.ivars.is_empty() {
+                    let consts = c.ivars.iter().map(|(ivar, ty, offset)| {
+                        let constname = Ident::new(&format!("IVAR_{}", ivar), Span::call_site());
+                        let offset = syn::LitInt::new(*offset, syn::IntSuffix::None, Span::call_site());
+                        let doc = if ty.is_objc_object() {
+                            format!(" Byte offset of the `{}` ivar, an object pointer.", ivar)
+                        } else {
+                            format!(" Byte offset of the `{}` ivar.", ivar)
+                        };
+                        quote!{
+                            #[doc = #doc]
+                            pub const #constname: usize = #offset;
+                        }
+                    });
+                    ast.items.push(parse_quote!{
+                        #[allow(non_upper_case_globals)]
+                        impl #name {
+                            #(#consts)*
+                        }
+                    });
+                }
+                for p in &c.all_protocols(decls) {
                     let protoname = format!("{}Proto", p);
+                    let proto = match decls.get(&protoname) {
+                        Some(ItemDecl::Proto(proto)) => proto,
+                        _ => continue,
+                    };
+                    // Required methods have no default in the trait, so
+                    // they're implemented here by messaging the object,
+                    // with the same cfgs the trait gave them.
+                    let proto_framework = gen_framework_name(&decls[&protoname]);
+                    let proto_framework = proto_framework.as_deref();
+                    let mut required: Vec<syn::ImplItem> = Vec::new();
+                    for (s, m) in &proto.imethods {
+                        if let Some(tokens) = m.gen_required_call(&decls, config, &proto.rustname, s) {
+                            let mut func = syn::parse2(tokens).unwrap();
+                            if let syn::ImplItem::Method(ref mut method) = func {
+                                // The trait's method carries the deprecation,
+                                // and it does nothing on an impl.
+                                method.attrs.retain(|a| !(a.path.segments.len() == 1 &&
+                                                          a.path.segments[0].ident == "deprecated"));
+                                if let Some(cfg) = gen_framework_sel_attr(decls, proto_framework, &m.refs()) {
+                                    method.attrs.push(cfg);
+                                }
+                                if let Some(framework) = proto_framework {
+                                    let feature_name = format!("RK_{}", framework);
+                                    method.attrs.push(parse_quote!(#[cfg(feature = #feature_name)]));
+                                }
+                            }
+                            required.push(func);
+                        }
+                    }
                     let proto = Ident::new(&protoname, Span::call_site());
                     ast.items.push(parse_quote!{
-                        impl #proto for #name {}
+                        impl #proto for #name {
+                            #(#required)*
+                        }
+                    });
+                }
+
+                // cmp reads the result as an NSInteger, so anything
+                // returning another integer type is left alone.
+                let comparable = match c.find_imethod(decls, "compare:") {
+                    Some(m) => m.args.len() == 1 && m.is_bindable(decls) && match m.retty {
+                        Type::Enum(ref e) => e == "NSComparisonResult",
+                        Type::Long(true) => true,
+                        _ => false,
+                    },
+                    None => false,
+                };
+                if comparable {
+                    let framework_feature_check = &framework_feature_check;
+                    ast.items.push(parse_quote!{
+                        #(#framework_feature_check)*
+                        impl Ord for Arc<#name> {
+                            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                                unsafe {
+                                    let send:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef,
+                                            *mut Object) -> isize =
+                                        mem::transmute(objc_msgSend as *const u8);
+                                    let _ret = send(
+                                        &**self as *const #name as *mut _,
+                                        SEL_compare_,
+                                        &**other as *const #name as *mut _,
+                                    );
+                                    _ret.cmp(&0)
+                                }
+                            }
+                        }
+                    });
+                    ast.items.push(parse_quote!{
+                        #(#framework_feature_check)*
+                        impl PartialOrd for Arc<#name> {
+                            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                                Some(self.cmp(other))
+                            }
+                        }
+                    });
+                }
+
+                // Everything conforming to the NSObject protocol has
+                // -isEqual: and -hash, which are what collections use, so
+                // they take precedence over -compare: for equality. Hash
+                // has to agree with PartialEq, so equality can't come from
+                // -compare: instead. Foundation's classes keep the two
+                // consistent, but nothing enforces it: NSString's -compare:
+                // treats composed character sequences as equal where
+                // -isEqual: doesn't. Ord is still only as consistent with
+                // Eq as the class makes it.
+                let equatable = c.all_protocols(decls).iter().any(|p| p == "NSObject");
+                let feature_check = &framework_feature_check;
+                if equatable {
+                    ast.items.push(parse_quote!{
+                        #(#feature_check)*
+                        impl PartialEq for Arc<#name> {
+                            fn eq(&self, other: &Self) -> bool {
+                                unsafe {
+                                    let send:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef,
+                                            *mut Object) -> BOOL =
+                                        mem::transmute(objc_msgSend as *const u8);
+                                    bool_from_objc(send(
+                                        &**self as *const #name as *mut _,
+                                        SEL_isEqual_,
+                                        &**other as *const #name as *mut _,
+                                    ))
+                                }
+                            }
+                        }
+                    });
+                    ast.items.push(parse_quote!{
+                        #(#feature_check)*
+                        impl std::hash::Hash for Arc<#name> {
+                            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                                let hash = unsafe {
+                                    let send:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef) -> usize =
+                                        mem::transmute(objc_msgSend as *const u8);
+                                    send(
+                                        &**self as *const #name as *mut _,
+                                        SEL_hash,
+                                    )
+                                };
+                                hash.hash(state);
+                            }
+                        }
+                    });
+                } else if comparable {
+                    ast.items.push(parse_quote!{
+                        #(#feature_check)*
+                        impl PartialEq for Arc<#name> {
+                            fn eq(&self, other: &Self) -> bool {
+                                self.cmp(other) == std::cmp::Ordering::Equal
+                            }
+                        }
+                    });
+                }
+                if equatable || comparable {
+                    ast.items.push(parse_quote!{
+                        #(#feature_check)*
+                        impl Eq for Arc<#name> {}
+                    });
+                }
+
+                // A mutable subclass (its superclass is already mutably
+                // copyable) copies into another mutable instance, since
+                // -copy would hand back the immutable superclass.
+                let protocols = c.all_protocols(decls);
+                let copy_sel = if protocols.iter().any(|p| p == "NSMutableCopying") &&
+                                  match decls.get(&c.superclass) {
+                                      Some(ItemDecl::Class(sc)) =>
+                                          sc.all_protocols(decls).iter().any(|p| p == "NSMutableCopying"),
+                                      _ => false,
+                                  } {
+                    Some("mutableCopy")
+                } else if protocols.iter().any(|p| p == "NSCopying") {
+                    Some("copy")
+                } else {
+                    None
+                };
+                if let Some(copy_sel) = copy_sel {
+                    let framework_feature_check = &framework_feature_check;
+                    let copy_ty = Ident::new(&c.copy_base(k, decls, copy_sel == "mutableCopy"),
+                                             Span::call_site());
+                    ast.items.push(parse_quote!{
+                        #(#framework_feature_check)*
+                        impl #name {
+                            /// Makes a new instance with -copy (or -mutableCopy
+                            /// for mutable classes), unlike `Arc::clone`, which
+                            /// shares this one. The copy is only known to be an
+                            /// instance of the class that adopted the protocol.
+                            #[must_use]
+                            pub fn deep_copy(&self) -> Arc<#copy_ty> {
+                                unsafe {
+                                    let send:
+                                        unsafe extern "C" fn(
+                                            *mut Object,
+                                            SelectorRef) -> *mut #copy_ty =
+                                        mem::transmute(objc_msgSend as *const u8);
+                                    let _ret = send(
+                                        self as *const Self as *mut Self as *mut _,
+                                        sel!(#copy_sel),
+                                    );
+                                    Arc::new_unchecked(_ret)
+                                }
+                            }
+                        }
                     });
                 }
 
+                let proto_sels = c.proto_selectors(decls);
                 let mut methods: Vec<syn::ImplItem> = Vec::new();
                 for (_, p) in &c.iprops {
                     if c.cmethods.contains_key(&p.getter) {
                         continue;
                     }
+                    if proto_sels.contains(&p.getter) {
+                        continue;
+                    }
                     if let Some(m) = &p.getter_method {
-                        if let Some(tokens) = m.gen_call(&decls, &p.getter, false) {
+                        let calls = m.gen_call(&decls, config, &c.rustname, &p.getter, false).into_iter().
+                            chain(m.gen_borrowed_call(&decls, config, &c.rustname, &p.getter, false));
+                        for tokens in calls {
                             let mut func = syn::parse2(tokens).unwrap();
                             if let syn::ImplItem::Method(ref mut method) = func {
                                 method.vis = parse_quote!{pub};
@@ -1744,7 +4139,11 @@ fn gen_file(
                         }
                     }
                     if let Some(m) = &p.setter_method {
-                        if let Some(tokens) = m.gen_call(&decls, p.setter.as_ref().unwrap(), false) {
+                        let setter = p.setter.as_ref().unwrap();
+                        if proto_sels.contains(setter) {
+                            continue;
+                        }
+                        if let Some(tokens) = m.gen_call(&decls, config, &c.rustname, setter, false) {
                             let mut func = syn::parse2(tokens).unwrap();
                             if let syn::ImplItem::Method(ref mut method) = func {
                                 method.vis = parse_quote!{pub};
@@ -1757,7 +4156,14 @@ fn gen_file(
                     }
                 }
                 for (s, m) in &c.cmethods {
-                    if let Some(tokens) = m.gen_call(&decls, s, true) {
+                    // Replaced by alloc() below, which can't be used
+                    // before initializing.
+                    if s == "alloc" || s == "allocWithZone:" {
+                        continue;
+                    }
+                    let calls = m.gen_call(&decls, config, &c.rustname, s, true).into_iter().
+                        chain(m.gen_raw_call(&decls, config, &c.rustname, s, true));
+                    for tokens in calls {
                         let mut func = syn::parse2(tokens).unwrap();
                         if let syn::ImplItem::Method(ref mut method) = func {
                             method.vis = parse_quote!{pub};
@@ -1769,21 +4175,51 @@ fn gen_file(
                     }
                 }
                 for (s, m) in &c.imethods {
-                    if c.cmethods.contains_key(s) {
+                    if c.cmethods.contains_key(s) || proto_sels.contains(s) {
                         continue;
                     }
-                    if let Some(tokens) = m.gen_call(&decls, s, false) {
+                    let calls = m.gen_call(&decls, config, &c.rustname, s, false).into_iter().
+                        chain(m.gen_raw_call(&decls, config, &c.rustname, s, false)).
+                        chain(m.gen_borrowed_call(&decls, config, &c.rustname, s, false)).
+                        chain(m.gen_checked_init(&decls, config, &c.rustname, s, false)).
+                        chain(m.gen_alloced_init(&decls, config, &c.rustname, s, false)).
+                        chain(m.gen_super_call(&decls, config, &c.rustname, s, false));
+                    for tokens in calls {
                         let mut func = syn::parse2(tokens).unwrap();
                         if let syn::ImplItem::Method(ref mut method) = func {
                             method.vis = parse_quote!{pub};
                             if let Some(cfg) = gen_framework_sel_attr(decls, framework_name, &m.refs()) {
                                 method.attrs.push(cfg);
                             }
+                            if m.designated {
+                                method.attrs.push(parse_quote!(#[doc = " Uses a designated initializer."]));
+                            }
                         }
                         methods.push(func);
                     }
                 }
 
+                // The initializers a subclass defined from Rust has to
+                // override, as selectors.
+                let mut designated: Vec<&str> = c.imethods.iter().
+                    filter(|(_, m)| m.designated).
+                    map(|(s, _)| s.as_str()).collect();
+                designated.sort();
+                if !designated.is_empty() {
+                    methods.push(parse_quote!{
+                        pub const DESIGNATED_INITIALIZERS: &'static [&'static str] = &[#(#designated),*];
+                    });
+                }
+
+                methods.push(parse_quote!{
+                    /// Allocates an instance without initializing it, to
+                    /// hand to one of the init... methods.
+                    #[must_use]
+                    pub fn alloc() -> Allocated<Self> {
+                        Allocated::new()
+                    }
+                });
+
                 let framework_feature_check = framework_feature_check.clone();
                 ast.items.push(parse_quote!{
                     #(#framework_feature_check)*
@@ -1801,9 +4237,23 @@ fn gen_file(
                     Ident::new(&k, Span::call_site());
                 let mut methods: Vec<syn::TraitItem> = Vec::new();
                 for (s, m) in &c.imethods {
-                    if let Some(tokens) = m.gen_call(&decls, s, false) {
+                    // Conforming types have to provide required methods
+                    // themselves. Only the wrapper around a `_raw` one
+                    // keeps its body.
+                    let error = m.returns_error(config, &c.rustname, s);
+                    let calls = m.gen_call(&decls, config, &c.rustname, s, false).
+                        map(|t| (t, !m.optional && !error)).into_iter().
+                        chain(m.gen_raw_call(&decls, config, &c.rustname, s, false).
+                              map(|t| (t, !m.optional))).
+                        chain(m.gen_responding_call(&decls, config, &c.rustname, s).
+                              map(|t| (t, false)));
+                    for (tokens, required) in calls {
                         let mut func = syn::parse2(tokens).unwrap();
                         if let syn::TraitItem::Method(ref mut method) = func {
+                            if required {
+                                method.default = None;
+                                method.semi_token = Some(Default::default());
+                            }
                             if let Some(cfg) = gen_framework_sel_attr(decls, framework_name, &m.refs()) {
                                 method.attrs.push(cfg);
                             }
@@ -1812,8 +4262,22 @@ fn gen_file(
                         methods.push(func);
                     }
                 }
+                // A protocol that (however indirectly) inherits back from
+                // this one would make the supertraits cyclic, which Rust
+                // rejects. Only the edges in a cycle going to a lexically
+                // greater name are kept, which breaks every cycle the same
+                // way each time while keeping as much of it as possible.
+                let supers: Vec<Ident> = c.protocols.iter().
+                    filter(|p| match decls.get(&format!("{}Proto", p)) {
+                        Some(ItemDecl::Proto(proto)) =>
+                            **p != c.rustname &&
+                            (c.rustname < **p ||
+                             !proto.all_protocols(decls).contains(&c.rustname)),
+                        _ => false,
+                    }).
+                    map(|p| Ident::new(&format!("{}Proto", p), Span::call_site())).collect();
                 ast.items.push(parse_quote!{
-                    pub trait #name: ObjCClass {
+                    pub trait #name: #(#supers +)* ObjCClass {
                         #(#methods)*
                     }
                 });
@@ -1835,7 +4299,12 @@ fn gen_file(
         if !f.src.starts_with(base_path) {
             return None;
         }
-        if f.args.iter().any(|(_, t)| t.is_va_list()) {
+        if f.args.iter().any(|(_, t)| t.is_va_list() || t.holds(decls, &Type::is_vector)) ||
+           f.retty.holds(decls, &Type::is_vector) {
+            return None;
+        }
+        if f.args.iter().any(|(_, t)| t.holds(decls, &Type::is_long_double)) ||
+           f.retty.holds(decls, &Type::is_long_double) {
             return None;
         }
         let name = Ident::new(&f.rustname, Span::call_site());
@@ -1856,9 +4325,20 @@ fn gen_file(
         if f.variadic {
             fndecl.decl.variadic = Some(syn::token::Dot3::new(Span::call_site()));
         }
+        if let Some(ref label) = f.link_name {
+            // Asm labels are the final symbol name, so tell LLVM not to
+            // add the platform's symbol prefix again with a leading \x01.
+            let label = format!("\x01{}", label);
+            fndecl.attrs.push(parse_quote!(#[link_name = #label]));
+        }
+        fndecl.attrs.extend(deprecated_attr(&f.avail));
+        if let Some(cfg) = gen_framework_sel_attr(decls, framework_name, &f.refs()) {
+            fndecl.attrs.push(cfg);
+        }
         Some(syn::ForeignItem::Fn(fndecl))
     }).collect();
 
+    let framework_name = framework_name.filter(|f| !config.weak_frameworks.contains(*f));
     if let Some(framework_name) = framework_name {
         ast.items.push(parse_quote!{
             #(#framework_feature_check)*
@@ -1869,14 +4349,312 @@ fn gen_file(
         });
     } else if !funcs.is_empty() {
         ast.items.push(parse_quote!{
+            #(#framework_feature_check)*
             extern "C" {
                 #(#funcs)*
             }
         });
     }
 
-    let mut f = File::create(out_path).unwrap();
-    f.write_fmt(format_args!("{}", ast.into_token_stream())).unwrap();
-    f.flush().unwrap();
-    std::process::Command::new("rustfmt").arg(out_path).status().unwrap();
+    // Only users of the bindings should be warned about deprecated APIs, not
+    // the bindings themselves. Headers are include!d, so they can't carry
+    // an inner attribute.
+    if file_mode {
+        for item in &mut ast.items {
+            if let Some(attrs) = item_attrs(item) {
+                attrs.push(parse_quote!(#[allow(deprecated)]));
+            }
+        }
+    } else {
+        ast.attrs.push(parse_quote!(#![allow(deprecated)]));
+    }
+
+    ast
+}
+
+fn item_attrs(item: &mut syn::Item) -> Option<&mut Vec<syn::Attribute>> {
+    match item {
+        syn::Item::Use(i) => Some(&mut i.attrs),
+        syn::Item::Static(i) => Some(&mut i.attrs),
+        syn::Item::Const(i) => Some(&mut i.attrs),
+        syn::Item::Fn(i) => Some(&mut i.attrs),
+        syn::Item::Mod(i) => Some(&mut i.attrs),
+        syn::Item::ForeignMod(i) => Some(&mut i.attrs),
+        syn::Item::Type(i) => Some(&mut i.attrs),
+        syn::Item::Struct(i) => Some(&mut i.attrs),
+        syn::Item::Enum(i) => Some(&mut i.attrs),
+        syn::Item::Union(i) => Some(&mut i.attrs),
+        syn::Item::Trait(i) => Some(&mut i.attrs),
+        syn::Item::Impl(i) => Some(&mut i.attrs),
+        syn::Item::Macro(i) => Some(&mut i.attrs),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_families() {
+        assert!(in_family("init", "init"));
+        assert!(in_family("initWithFrame:", "init"));
+        assert!(in_family("_initWithCoder:", "init"));
+        assert!(!in_family("initialize", "init"));
+        assert!(!in_family("copyright", "copy"));
+        assert!(returns_retained_by_family("newObject"));
+        assert!(returns_retained_by_family("mutableCopyWithZone:"));
+        assert!(!returns_retained_by_family("newline"));
+    }
+
+    #[test]
+    fn snake_case_acronyms() {
+        assert_eq!(snake_case_selector("URLByAppendingPathComponent:"), "url_by_appending_path_component");
+        assert_eq!(snake_case_selector("fileURLWithPath:"), "file_url_with_path");
+        assert_eq!(snake_case_selector("URLsForDirectory:inDomains:"), "urls_for_directory_in_domains");
+        assert_eq!(snake_case_selector("setHTTPBody:"), "set_http_body");
+        assert_eq!(snake_case_selector("UTF8String"), "utf8_string");
+    }
+
+    #[test]
+    fn snake_case_colons() {
+        assert_eq!(snake_case_selector("count"), "count");
+        assert_eq!(snake_case_selector("objectAtIndex:"), "object_at_index");
+        assert_eq!(snake_case_selector("setValue:forKey:"), "set_value_for_key");
+        assert_eq!(snake_case_selector("initWithObjects:count:"), "init_with_objects_count");
+    }
+
+    fn method(rustname: &str) -> MethodDecl {
+        MethodDecl {
+            rustname: rustname.to_owned(),
+            avail: walker::Availability::Available,
+            args: Vec::new(),
+            retty: Type::Void,
+            ret_own: ReturnOwnership::NotRetained,
+            inter_ptr: false,
+            consumes_self: false,
+            optional: false,
+            variadic: false,
+            nil_terminated: false,
+            designated: false,
+        }
+    }
+
+    fn property(name: &str, ty: Type, getter: &str, setter: &str) -> PropertyDecl {
+        PropertyDecl {
+            name: name.to_owned(),
+            ty: ty,
+            getter: getter.to_owned(),
+            setter: Some(setter.to_owned()),
+            weak: false,
+            getter_method: None,
+            setter_method: None,
+        }
+    }
+
+    fn class() -> ClassDecl {
+        ClassDecl {
+            src: PathBuf::new(),
+            rustname: "RKTest".to_owned(),
+            superclass: String::new(),
+            size: None,
+            exception: false,
+            protocols: Vec::new(),
+            cprops: HashMap::new(),
+            iprops: HashMap::new(),
+            cmethods: HashMap::new(),
+            imethods: HashMap::new(),
+            ivars: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn name_collisions() {
+        let mut c = class();
+        for s in &["foo", "foo:", "setValue:forKey:", "setValueForKey:", "bar"] {
+            c.imethods.insert(s.to_string(), method(&snake_case_selector(s)));
+        }
+        c.cmethods.insert("bar".to_owned(), method("bar"));
+        c.resolve_name_collisions();
+        assert_eq!(c.imethods["foo"].rustname, "foo");
+        assert_eq!(c.imethods["foo:"].rustname, "foo_");
+        assert_eq!(c.imethods["setValue:forKey:"].rustname, "setValue_forKey_");
+        assert_eq!(c.imethods["setValueForKey:"].rustname, "setValueForKey_");
+        // The same selector as a class and an instance method is only
+        // bound once, so it isn't a collision.
+        assert_eq!(c.imethods["bar"].rustname, "bar");
+    }
+
+    #[test]
+    fn property_accessor_names() {
+        let config = Config { property_accessors: true, ..Default::default() };
+        let mut c = class();
+        let mut hidden = property("hidden", Type::ObjCBool, "isHidden", "setHidden:");
+        hidden.set_getter(method("isHidden"), &config);
+        hidden.set_setter(method("setHidden_"), &config);
+        let mut title = property("titleColor", Type::Void, "titleColor", "setTitleColor:");
+        title.set_getter(method("titleColor"), &config);
+        title.set_setter(method("setTitleColor_"), &config);
+        c.iprops.insert("hidden".to_owned(), hidden);
+        c.iprops.insert("titleColor".to_owned(), title);
+        // Already taken by a method, so the accessor keeps its selector's
+        // name instead.
+        c.imethods.insert("set_title_color".to_owned(), method("set_title_color"));
+        c.resolve_name_collisions();
+        let hidden = &c.iprops["hidden"];
+        assert_eq!(hidden.getter_method.as_ref().unwrap().rustname, "hidden");
+        assert_eq!(hidden.setter_method.as_ref().unwrap().rustname, "set_hidden");
+        let title = &c.iprops["titleColor"];
+        assert_eq!(title.getter_method.as_ref().unwrap().rustname, "title_color");
+        assert_eq!(title.setter_method.as_ref().unwrap().rustname, "setTitleColor_");
+        assert_eq!(c.imethods["set_title_color"].rustname, "set_title_color");
+    }
+
+    fn arg(name: &str, ty: Type) -> Arg {
+        Arg { name: name.to_owned(), ty: ty, consumed: false }
+    }
+
+    fn params(m: &MethodDecl) -> String {
+        let params = m.rust_params();
+        quote!(#(#params),*).to_string()
+    }
+
+    #[test]
+    fn slice_args() {
+        let array = Type::IncompleteArray(Box::new(Type::Int(true, 4)), true, true);
+        let mut m = method("set_values_count");
+        m.args = vec![arg("values", array.clone()), arg("count", Type::Long(false))];
+        assert!(m.is_slice_arg(0));
+        assert!(m.is_slice_len_arg(1));
+        assert_eq!(params(&m), quote!(values: &[i32]).to_string());
+        m.args[1].name = "maxLength".to_owned();
+        assert!(!m.is_slice_arg(0));
+        let raw = array.rust_ty(false);
+        assert_eq!(params(&m), quote!(values: #raw, maxLength: usize).to_string());
+    }
+
+    #[test]
+    fn count_names() {
+        for name in &["count", "len", "length", "n", "objectCount", "numItems"] {
+            assert!(is_count_name(name), "{}", name);
+        }
+        for name in &["maxLength", "lengthLimit", "numberOfBytesPerRow", "maxCount", "index", "options"] {
+            assert!(!is_count_name(name), "{}", name);
+        }
+    }
+
+    fn deprecated(msg: &str) -> walker::Availability {
+        walker::Availability::Deprecated(msg.to_owned())
+    }
+
+    #[test]
+    fn deprecation_attrs() {
+        assert!(deprecated_attr(&walker::Availability::Available).is_none());
+        let attr = deprecated_attr(&deprecated(""));
+        assert_eq!(quote!(#attr).to_string(), quote!(#[deprecated]).to_string());
+        let note = quote!(#[deprecated(note = "Use -newValue")]).to_string();
+        let attr = deprecated_attr(&deprecated("Use -newValue"));
+        assert_eq!(quote!(#attr).to_string(), note);
+
+        let mut m = method("oldValue");
+        m.avail = deprecated("Use -newValue");
+        let call = m.gen_call(&HashMap::new(), &Config::default(), "RKTest", "oldValue", false);
+        assert!(call.unwrap().to_string().contains(&note));
+
+        let src = PathBuf::from("/headers/RKTest.h");
+        let mut decls = HashMap::new();
+        decls.insert("RKOldEnum".to_owned(), ItemDecl::Enum(EnumDecl {
+            src: src.clone(),
+            rustname: "RKOldEnum".to_owned(),
+            avail: deprecated(""),
+            ty: Type::Long(true),
+            exhaustive: true,
+            flagenum: false,
+            variants: vec![("RKOldEnumValue".to_owned(), 0, false)],
+        }));
+        decls.insert("RKOldFunction".to_owned(), ItemDecl::Func(FunctionDecl {
+            src: src,
+            rustname: "RKOldFunction".to_owned(),
+            avail: deprecated("Use RKNewFunction"),
+            args: Vec::new(),
+            retty: Type::Void,
+            variadic: false,
+            link_name: None,
+        }));
+        let names = vec!["RKOldEnum".to_owned(), "RKOldFunction".to_owned()];
+        let ast = gen_ast(&decls, &names, Path::new("/headers"), &[], None, true,
+                          &mut HashSet::new(), &Config::default());
+        let out = ast.into_token_stream().to_string();
+        let bare = quote!(#[deprecated] #[repr(isize)]).to_string();
+        assert!(out.contains(&bare), "{}", out);
+        let note = quote!(#[deprecated(note = "Use RKNewFunction")] pub fn RKOldFunction).to_string();
+        assert!(out.contains(&note), "{}", out);
+    }
+
+    #[test]
+    fn repeated_arg_names() {
+        let mut seen = HashSet::new();
+        let names: Vec<_> = ["value", "value_2", "value", "value"].iter().
+            map(|n| unique_arg_name(n.to_string(), &mut seen)).
+            collect();
+        assert_eq!(names, ["value", "value_2", "value_3", "value_4"]);
+    }
+
+    #[test]
+    fn target_layout() {
+        let config = |target: &str| Config { target: Some(target.to_owned()), ..Default::default() };
+        assert_eq!(config("x86_64-apple-darwin").pointer_width(), 8);
+        assert_eq!(config("aarch64-apple-darwin").pointer_width(), 8);
+        assert_eq!(config("arm64_32-apple-watchos").pointer_width(), 4);
+        assert_eq!(config("armv7k-apple-watchos").pointer_width(), 4);
+        assert_eq!(config("aarch64-apple-ios-sim").clang_target().unwrap(), "aarch64-apple-ios-simulator");
+        assert_eq!(config("aarch64-apple-ios").clang_target().unwrap(), "aarch64-apple-ios");
+    }
+
+    #[test]
+    fn sdk_paths() {
+        let sdk = |target| {
+            let path = default_sdk_path(target);
+            path.file_stem().unwrap().to_str().unwrap().to_owned()
+        };
+        assert_eq!(sdk(None), "MacOSX");
+        assert_eq!(sdk(Some("aarch64-apple-darwin")), "MacOSX");
+        assert_eq!(sdk(Some("aarch64-apple-ios-macabi")), "MacOSX");
+        assert_eq!(sdk(Some("aarch64-apple-ios")), "iPhoneOS");
+        assert_eq!(sdk(Some("x86_64-apple-ios")), "iPhoneSimulator");
+        assert_eq!(sdk(Some("aarch64-apple-tvos")), "AppleTVOS");
+        assert_eq!(sdk(Some("aarch64-apple-tvos-sim")), "AppleTVSimulator");
+        assert_eq!(sdk(Some("arm64_32-apple-watchos")), "WatchOS");
+        assert_eq!(sdk(Some("x86_64-apple-watchos-sim")), "WatchSimulator");
+    }
+
+    fn introduced(platform: &str, major: i32, minor: i32) -> walker::AvailabilityAttr {
+        let none = clang::CXVersion { Major: -1, Minor: -1, Subminor: -1 };
+        walker::AvailabilityAttr {
+            platform: platform.to_owned(),
+            introduced: clang::CXVersion { Major: major, Minor: minor, Subminor: -1 },
+            deprecated: none,
+            obsoleted: none,
+            unavailable: false,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn deployment_target_availability() {
+        let attrs = [introduced("macos", 99, 0), introduced("ios", 12, -1)];
+        let unset = Config::default();
+        assert_eq!(introduced_after(&attrs, &unset), None);
+        let macos = Config { deployment_target: Some((10, 13)), ..Default::default() };
+        assert_eq!(introduced_after(&attrs, &macos), Some((99, 0)));
+        let ios = |v| Config {
+            target: Some("aarch64-apple-ios".to_owned()),
+            deployment_target: Some(v),
+            ..Default::default()
+        };
+        assert_eq!(introduced_after(&attrs, &ios((11, 4))), Some((12, 0)));
+        assert_eq!(introduced_after(&attrs, &ios((12, 0))), None);
+        // Nothing says when it came to other platforms, so it's bound.
+        assert_eq!(introduced_after(&[introduced("watchos", 99, 0)], &macos), None);
+    }
 }
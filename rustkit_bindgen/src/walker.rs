@@ -4,6 +4,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ptr;
 use std::path::PathBuf;
 use std::marker::PhantomData;
@@ -736,7 +738,7 @@ pub enum TypeKind {
     Attributed,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Nullability {
     NonNull,
     Nullable,
@@ -939,6 +941,13 @@ pub struct Ty {
     t: CXType,
 }
 
+thread_local! {
+    // The same types get laid out over and over while binding a
+    // framework, so sizes are cached by canonical type for as long as
+    // the translation unit they came from is alive.
+    static SIZE_CACHE: RefCell<HashMap<(usize, usize), i64>> = RefCell::new(HashMap::new());
+}
+
 impl Ty {
     #[allow(non_upper_case_globals)]
     pub fn kind(&self) -> TypeKind {
@@ -1055,6 +1064,10 @@ impl Ty {
         into_str(unsafe { clang_getTypeSpelling(self.t) })
     }
 
+    pub fn objc_encoding(&self) -> String {
+        into_str(unsafe { clang_Type_getObjCEncoding(self.t) })
+    }
+
     pub fn canonical(&self) -> Ty {
         Ty {
             t: unsafe { clang_getCanonicalType(self.t) }
@@ -1163,6 +1176,21 @@ impl Ty {
         }
     }
 
+    // Element type of complex and vector types
+    pub fn component_ty(&self) -> Ty {
+        Ty {
+            t: unsafe { clang_getElementType(self.t) }
+        }
+    }
+
+    pub fn num_elements(&self) -> u64 {
+        let size = unsafe { clang_getNumElements(self.t) };
+        if size < 0 {
+            panic!("Negative element count???");
+        }
+        size as u64
+    }
+
     pub fn array_size(&self) -> u64 {
         let size = unsafe { clang_getArraySize(self.t) };
         if size < 0 {
@@ -1172,11 +1200,26 @@ impl Ty {
     }
 
     pub fn size(&self) -> u64 {
-        let size = unsafe { clang_Type_getSizeOf(self.t) };
+        match self.try_size() {
+            Some(size) => size,
+            None => panic!("Negative type size???"),
+        }
+    }
+
+    // None if clang can't lay the type out, e.g. because it's incomplete.
+    pub fn try_size(&self) -> Option<u64> {
+        let canon = unsafe { clang_getCanonicalType(self.t) };
+        let key = (canon.data[0] as usize, canon.data[1] as usize);
+        let size = SIZE_CACHE.with(|cache| {
+            *cache.borrow_mut().entry(key).or_insert_with(|| {
+                unsafe { clang_Type_getSizeOf(self.t) }
+            })
+        });
         if size < 0 {
-            panic!("Negative type size???");
+            None
+        } else {
+            Some(size as u64)
         }
-        size as u64
     }
 
     pub fn num_protocols(&self) -> u32 {
@@ -1277,6 +1320,22 @@ impl Cursor {
         SourceLocation { loc: unsafe { clang_getCursorLocation(self.c) } }
     }
 
+    // Byte offset of a field or ivar from the start of its record. None if
+    // clang can't lay it out, or if it's a bitfield that doesn't start on a
+    // byte boundary.
+    pub fn offset_of_field(&self) -> Option<u64> {
+        let bits = unsafe { clang_Cursor_getOffsetOfField(self.c) };
+        if bits < 0 || bits % 8 != 0 {
+            None
+        } else {
+            Some(bits as u64 / 8)
+        }
+    }
+
+    pub fn is_bit_field(&self) -> bool {
+        unsafe { clang_Cursor_isBitField(self.c) != 0 }
+    }
+
     pub fn property_attributes(&self) -> PropertyAttributes {
         PropertyAttributes {
             attr: unsafe { clang_Cursor_getObjCPropertyAttributes(self.c, 0) },
@@ -1299,6 +1358,11 @@ impl Cursor {
         unsafe { clang_Cursor_isVariadic(self.c) != 0 }
     }
 
+    // Whether a protocol's method or property was declared under @optional.
+    pub fn is_objc_optional(&self) -> bool {
+        unsafe { clang_Cursor_isObjCOptional(self.c) != 0 }
+    }
+
     #[allow(non_upper_case_globals)]
     pub fn availability(&self) -> Availability {
         let avail = unsafe { clang_getCursorAvailability(self.c) };
@@ -1410,6 +1474,20 @@ impl Cursor {
         unsafe { clang_getEnumConstantDeclUnsignedValue(self.c) }
     }
 
+    pub fn tokens(&self) -> Vec<String> {
+        let mut tokens: *mut CXToken = ptr::null_mut();
+        let mut len = 0;
+        unsafe {
+            let tu = clang_Cursor_getTranslationUnit(self.c);
+            clang_tokenize(tu, clang_getCursorExtent(self.c), &mut tokens as *mut _, &mut len as *mut _);
+            let spellings = (0..len).map(|i| {
+                into_str(clang_getTokenSpelling(tu, *tokens.offset(i as isize)))
+            }).collect();
+            clang_disposeTokens(tu, tokens, len);
+            spellings
+        }
+    }
+
     pub fn visit_children<V>(&self, mut cb: V)
         where V: FnMut(Cursor) -> ChildVisit {
         unsafe {
@@ -1446,6 +1524,8 @@ impl<'a> TranslationUnit<'a> {
 
 impl<'a> Drop for TranslationUnit<'a> {
     fn drop(&mut self) {
+        let tu = self.tu as usize;
+        SIZE_CACHE.with(|cache| cache.borrow_mut().retain(|k, _| k.1 != tu));
         unsafe {
             clang_disposeTranslationUnit(self.tu);
         }
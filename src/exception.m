@@ -0,0 +1,23 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#import <objc/runtime.h>
+#import <objc/NSObject.h>
+
+// Rust can't catch ObjC exceptions itself, so objc::catch runs its
+// closure through here. Exceptions of the given class come back retained,
+// and anything else carries on as if it had never been caught.
+id rustkit_try(void (*f)(void *), void *ctx, Class cls) {
+    @try {
+        f(ctx);
+    } @catch (id e) {
+        if (![e isKindOfClass:cls]) {
+            @throw;
+        }
+        return [e retain];
+    }
+    return nil;
+}
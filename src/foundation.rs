@@ -0,0 +1,550 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Conveniences that can't be expressed by the generated Foundation
+ * bindings, usually because the underlying ObjC API wants a C array or
+ * some other shape that doesn't map onto a plain Rust argument.
+ */
+
+use std::ffi::CStr;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Range;
+use std::ptr;
+use std::slice;
+use std::str;
+use objc::*;
+use Foundation::{NSArray, NSData, NSDictionary, NSMutableString, NSNumber, NSRange, NSString, NSValue};
+
+impl NSArray {
+    /* The array retains its elements, so the Arcs only need to be
+     * borrowed for the duration of the call. Arc<T> is a transparent
+     * wrapper around the object pointer, which lets the slice be passed
+     * straight through as an id[].
+     */
+    pub fn from_slice<T>(objects: &[Arc<T>]) -> Arc<NSArray> {
+        let objs = if objects.is_empty() {
+            ptr::null()
+        } else {
+            objects.as_ptr()
+        };
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    *const Arc<T>,
+                    usize) -> *mut NSArray =
+                mem::transmute(objc_msgSend as *const u8);
+            let _ret = send(
+                <NSArray as ObjCClass>::classref().0 as *const Object as *mut _,
+                sel!("arrayWithObjects:count:"),
+                objs,
+                objects.len()
+            );
+            retain_autoreleased!(_ret as *mut _);
+            Arc::new_unchecked(_ret)
+        }
+    }
+
+    pub fn iter(&self) -> NSArrayIter {
+        NSArrayIter {
+            array: self,
+            index: 0,
+            len: self.count(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<Arc<Object>> {
+        self.iter().collect()
+    }
+}
+
+/* Walks the array by index with -objectAtIndex:, retaining each element
+ * so it stays alive even if the array lets go of it. The length is taken
+ * up front, so mutating the array while iterating can fail with a range
+ * exception. FastEnumerator is faster, but only hands out borrows.
+ */
+pub struct NSArrayIter<'a> {
+    array: &'a NSArray,
+    index: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for NSArrayIter<'a> {
+    type Item = Arc<Object>;
+
+    fn next(&mut self) -> Option<Arc<Object>> {
+        if self.index == self.len {
+            return None;
+        }
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    usize) -> *mut Object =
+                mem::transmute(objc_msgSend as *const u8);
+            let obj = send(
+                self.array as *const NSArray as *mut NSArray as *mut _,
+                sel!("objectAtIndex:"),
+                self.index
+            );
+            self.index += 1;
+            objc_retain(obj);
+            Some(Arc::new_unchecked(obj))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for NSArrayIter<'a> {}
+
+impl<'a> IntoIterator for &'a NSArray {
+    type Item = Arc<Object>;
+    type IntoIter = NSArrayIter<'a>;
+
+    fn into_iter(self) -> NSArrayIter<'a> {
+        self.iter()
+    }
+}
+
+impl<T> FromIterator<Arc<T>> for Arc<NSArray> {
+    fn from_iter<I: IntoIterator<Item = Arc<T>>>(iter: I) -> Arc<NSArray> {
+        let objects: Vec<Arc<T>> = iter.into_iter().collect();
+        NSArray::from_slice(&objects)
+    }
+}
+
+/* Neither -objectForKey: nor -nextObject hand back a reference of their
+ * own. The objects are only kept alive by the dictionary (or for keys, by
+ * its enumerator), so everything returned here is retained first, and
+ * stays valid after the dictionary goes away.
+ */
+impl NSDictionary {
+    pub fn get(&self, key: &str) -> Option<Arc<Object>> {
+        let key: Arc<NSString> = key.into();
+        unsafe { self.object_for_key(&*key as *const NSString as *mut Object) }
+    }
+
+    unsafe fn object_for_key(&self, key: *mut Object) -> Option<Arc<Object>> {
+        let send:
+            unsafe extern "C" fn(
+                *mut Object,
+                SelectorRef,
+                *mut Object) -> *mut Object =
+            mem::transmute(objc_msgSend as *const u8);
+        let obj = send(
+            self as *const Self as *mut Self as *mut _,
+            sel!("objectForKey:"),
+            key
+        );
+        if obj.is_null() {
+            return None;
+        }
+        objc_retain(obj);
+        Some(Arc::new_unchecked(obj))
+    }
+
+    pub fn iter(&self) -> NSDictionaryIter {
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef) -> *mut Object =
+                mem::transmute(objc_msgSend as *const u8);
+            let keys = send(
+                self as *const Self as *mut Self as *mut _,
+                sel!("keyEnumerator")
+            );
+            retain_autoreleased!(keys);
+            NSDictionaryIter {
+                dict: self,
+                keys: Arc::new_unchecked(keys),
+            }
+        }
+    }
+}
+
+/* Yields (key, value) pairs, looking each value up as its key comes out
+ * of -keyEnumerator. Mutating the dictionary while iterating throws.
+ */
+pub struct NSDictionaryIter<'a> {
+    dict: &'a NSDictionary,
+    keys: Arc<Object>,
+}
+
+impl<'a> Iterator for NSDictionaryIter<'a> {
+    type Item = (Arc<Object>, Arc<Object>);
+
+    fn next(&mut self) -> Option<(Arc<Object>, Arc<Object>)> {
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef) -> *mut Object =
+                mem::transmute(objc_msgSend as *const u8);
+            let key = send(
+                &*self.keys as *const Object as *mut _,
+                sel!("nextObject")
+            );
+            if key.is_null() {
+                return None;
+            }
+            objc_retain(key);
+            let key = Arc::new_unchecked(key);
+            let value = self.dict.object_for_key(&*key as *const Object as *mut _).
+                expect("Dictionary key has no value");
+            Some((key, value))
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a NSDictionary {
+    type Item = (Arc<Object>, Arc<Object>);
+    type IntoIter = NSDictionaryIter<'a>;
+
+    fn into_iter(self) -> NSDictionaryIter<'a> {
+        self.iter()
+    }
+}
+
+impl NSData {
+    pub fn from_bytes(bytes: &[u8]) -> Arc<NSData> {
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    *const u8,
+                    usize) -> *mut NSData =
+                mem::transmute(objc_msgSend as *const u8);
+            let _ret = send(
+                <NSData as ObjCClass>::classref().0 as *const Object as *mut _,
+                sel!("dataWithBytes:length:"),
+                bytes.as_ptr(),
+                bytes.len()
+            );
+            retain_autoreleased!(_ret as *mut _);
+            Arc::new_unchecked(_ret)
+        }
+    }
+
+    /* -bytes is an inner pointer, so the slice can't outlive the borrow
+     * of the NSData that owns it. Empty data is allowed to hand back a
+     * null pointer, which from_raw_parts doesn't accept.
+     */
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.length();
+        if len == 0 {
+            return &[];
+        }
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef) -> *const u8 =
+                mem::transmute(objc_msgSend as *const u8);
+            let bytes = send(
+                self as *const Self as *mut Self as *mut _,
+                sel!("bytes")
+            );
+            slice::from_raw_parts(bytes, len)
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl From<Range<usize>> for NSRange {
+    fn from(r: Range<usize>) -> NSRange {
+        assert!(r.start <= r.end, "range start {} is past its end {}", r.start, r.end);
+        NSRange {
+            location: r.start,
+            length: r.end - r.start,
+        }
+    }
+}
+
+impl NSRange {
+    pub fn to_range(&self) -> Range<usize> {
+        self.location..self.location + self.length
+    }
+}
+
+/* Boxing Rust values into their Foundation counterparts. */
+
+// NSUTF8StringEncoding
+const UTF8_ENCODING: usize = 4;
+
+impl<'a> From<&'a str> for Arc<NSString> {
+    fn from(s: &'a str) -> Arc<NSString> {
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    *const u8,
+                    usize,
+                    usize) -> *mut NSString =
+                mem::transmute(objc_msgSend as *const u8);
+            let _ret = send(
+                alloc_with_zone(<NSString as ObjCClass>::classref()),
+                sel!("initWithBytes:length:encoding:"),
+                s.as_ptr(),
+                s.len(),
+                UTF8_ENCODING
+            );
+            Arc::new_unchecked(_ret)
+        }
+    }
+}
+
+macro_rules! number_from {
+    ( $ty:ty, $sel:expr ) => {
+        impl From<$ty> for Arc<NSNumber> {
+            fn from(v: $ty) -> Arc<NSNumber> {
+                unsafe {
+                    let send:
+                        unsafe extern "C" fn(
+                            *mut Object,
+                            SelectorRef,
+                            $ty) -> *mut NSNumber =
+                        mem::transmute(objc_msgSend as *const u8);
+                    let _ret = send(
+                        <NSNumber as ObjCClass>::classref().0 as *const Object as *mut _,
+                        sel!($sel),
+                        v
+                    );
+                    retain_autoreleased!(_ret as *mut _);
+                    Arc::new_unchecked(_ret)
+                }
+            }
+        }
+    }
+}
+
+number_from!(bool, "numberWithBool:");
+number_from!(i32, "numberWithInt:");
+number_from!(u32, "numberWithUnsignedInt:");
+number_from!(i64, "numberWithLongLong:");
+number_from!(u64, "numberWithUnsignedLongLong:");
+number_from!(isize, "numberWithInteger:");
+number_from!(usize, "numberWithUnsignedInteger:");
+number_from!(f32, "numberWithFloat:");
+number_from!(f64, "numberWithDouble:");
+
+impl From<NSRange> for Arc<NSValue> {
+    fn from(r: NSRange) -> Arc<NSValue> {
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    NSRange) -> *mut NSValue =
+                mem::transmute(objc_msgSend as *const u8);
+            let _ret = send(
+                <NSValue as ObjCClass>::classref().0 as *const Object as *mut _,
+                sel!("valueWithRange:"),
+                r
+            );
+            retain_autoreleased!(_ret as *mut _);
+            Arc::new_unchecked(_ret)
+        }
+    }
+}
+
+impl<'a, T> From<&'a [Arc<T>]> for Arc<NSArray> {
+    fn from(objects: &'a [Arc<T>]) -> Arc<NSArray> {
+        NSArray::from_slice(objects)
+    }
+}
+
+impl<'a, K, V> From<&'a [(Arc<K>, Arc<V>)]> for Arc<NSDictionary> {
+    /* The dictionary copies its keys and retains its values, so like
+     * NSArray::from_slice the pairs only need to be borrowed.
+     */
+    fn from(pairs: &'a [(Arc<K>, Arc<V>)]) -> Arc<NSDictionary> {
+        let keys: Vec<*const K> = pairs.iter().map(|(k, _)| &**k as *const K).collect();
+        let objs: Vec<*const V> = pairs.iter().map(|(_, v)| &**v as *const V).collect();
+        unsafe {
+            let send:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    *const *const V,
+                    *const *const K,
+                    usize) -> *mut NSDictionary =
+                mem::transmute(objc_msgSend as *const u8);
+            let _ret = send(
+                <NSDictionary as ObjCClass>::classref().0 as *const Object as *mut _,
+                sel!("dictionaryWithObjects:forKeys:count:"),
+                objs.as_ptr(),
+                keys.as_ptr(),
+                pairs.len()
+            );
+            retain_autoreleased!(_ret as *mut _);
+            Arc::new_unchecked(_ret)
+        }
+    }
+}
+
+impl NSString {
+    /* Borrows the string's UTF-8 contents without copying them, which is
+     * the common case. -UTF8String stops at the first NUL and can fail
+     * outright, so anything it can't represent exactly goes through a
+     * lossy copy instead.
+     */
+    pub fn with_utf8<R, F: FnOnce(&str) -> R>(&self, f: F) -> R {
+        unsafe {
+            let utf8:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef) -> *const i8 =
+                mem::transmute(objc_msgSend as *const u8);
+            let bytes = utf8(
+                self as *const Self as *mut Self as *mut _,
+                sel!("UTF8String")
+            );
+            let byte_len:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    usize) -> usize =
+                mem::transmute(objc_msgSend as *const u8);
+            let len = byte_len(
+                self as *const Self as *mut Self as *mut _,
+                sel!("lengthOfBytesUsingEncoding:"),
+                UTF8_ENCODING
+            );
+            if !bytes.is_null() {
+                let bytes = CStr::from_ptr(bytes).to_bytes();
+                if bytes.len() == len {
+                    if let Ok(s) = str::from_utf8(bytes) {
+                        return f(s);
+                    }
+                }
+            }
+
+            let data:
+                unsafe extern "C" fn(
+                    *mut Object,
+                    SelectorRef,
+                    usize,
+                    bool) -> *mut NSData =
+                mem::transmute(objc_msgSend as *const u8);
+            let data = data(
+                self as *const Self as *mut Self as *mut _,
+                sel!("dataUsingEncoding:allowLossyConversion:"),
+                UTF8_ENCODING,
+                true
+            );
+            retain_autoreleased!(data as *mut _);
+            match Arc::new(data) {
+                Some(data) => f(&String::from_utf8_lossy(data.as_bytes())),
+                None => f(""),
+            }
+        }
+    }
+
+    /* Same as converting with into(), which goes by byte length, so
+     * embedded NULs survive.
+     */
+    pub fn from_str(s: &str) -> Arc<NSString> {
+        s.into()
+    }
+}
+
+/* Both write the UTF-8 contents as with_utf8 gets them, which also gives
+ * NSString a to_string(). Debug quotes and escapes them like a str.
+ */
+impl fmt::Display for NSString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_utf8(|s| fmt::Display::fmt(s, f))
+    }
+}
+
+impl fmt::Debug for NSString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_utf8(|s| fmt::Debug::fmt(s, f))
+    }
+}
+
+impl NSMutableString {
+    fn as_nsstring(&self) -> &NSString {
+        unsafe { &*(self as *const NSMutableString as *const NSString) }
+    }
+}
+
+impl fmt::Display for NSMutableString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_nsstring(), f)
+    }
+}
+
+impl fmt::Debug for NSMutableString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_nsstring(), f)
+    }
+}
+
+/* Toll-free bridged types are the same object under both names, so going
+ * between them is just a pointer cast. Only pairs whose CF side has been
+ * generated can be listed here, since the CF types are opaque records that
+ * only exist once something references them.
+ */
+macro_rules! toll_free_bridge {
+    ( $( $ns:ident <=> $cf:ident ),* ) => {
+        $(
+            #[cfg(feature = "RK_CoreFoundation")]
+            impl ::Foundation::$ns {
+                /// Borrows this as its Core Foundation counterpart, without
+                /// retaining it.
+                pub fn as_cf(&self) -> *const ::CoreFoundation::$cf {
+                    self as *const ::Foundation::$ns as *const _
+                }
+
+                /// Retains a Core Foundation reference as its Foundation
+                /// counterpart. Unsafe since nothing checks the pointer.
+                pub unsafe fn from_cf(cf: *const ::CoreFoundation::$cf) -> Option<Arc<::Foundation::$ns>> {
+                    if cf.is_null() {
+                        return None;
+                    }
+                    objc_retain(cf as *mut Object);
+                    Arc::new(cf as *mut ::Foundation::$ns)
+                }
+            }
+
+            /// Hands the reference over to Core Foundation, which has to
+            /// CFRelease it.
+            #[cfg(feature = "RK_CoreFoundation")]
+            impl From<Arc<::Foundation::$ns>> for *const ::CoreFoundation::$cf {
+                fn from(obj: Arc<::Foundation::$ns>) -> *const ::CoreFoundation::$cf {
+                    let cf = obj.as_cf();
+                    mem::forget(obj);
+                    cf
+                }
+            }
+        )*
+    }
+}
+
+toll_free_bridge! {
+    NSArray <=> __CFArray,
+    NSData <=> __CFData,
+    NSDate <=> __CFDate,
+    NSDictionary <=> __CFDictionary,
+    NSNumber <=> __CFNumber,
+    NSString <=> __CFString,
+    NSURL <=> __CFURL
+}
@@ -1,7 +1,12 @@
 #[macro_use]
 extern crate bitflags;
 
+#[macro_use]
 pub mod objc;
+#[cfg(feature = "RK_Foundation")]
+mod foundation;
+#[cfg(feature = "RK_Foundation")]
+pub use foundation::{NSArrayIter, NSDictionaryIter};
 
 use std::mem;
 use std::ptr;
@@ -13,6 +18,26 @@ pub struct FILE {
 pub struct c_void {
     opaque: [u8; 0]
 }
+/* Same layout as C's _Complex float/double */
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Complex<T> {
+    pub re: T,
+    pub im: T,
+}
+/* Same size and alignment as C's long double/__float128 where they're
+ * wider than a double, for pointers to them. Rust has no matching float
+ * type, and this isn't passed the way they are, so nothing taking or
+ * returning one by value is bound. The bits have to be converted by hand. */
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LongDouble(pub [u8; 16]);
+/* CoreGraphics' CGFloat follows the pointer width, and the bindings use
+ * this instead of whatever it was when the headers were parsed. */
+#[cfg(target_pointer_width = "64")]
+pub type CGFloat = f64;
+#[cfg(target_pointer_width = "32")]
+pub type CGFloat = f32;
 
 /* This probably won't work for bitcode. Need to use LLVM IR metadata.
  * See llvm/docs/LangRef.rst */
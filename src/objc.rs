@@ -4,8 +4,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::UnsafeCell;
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::marker::PhantomData;
+use std::ptr;
 use std::ptr::NonNull;
 use std::ops::Deref;
+use std::sync::Once;
 
 /* We use a macro instead of a struct so the user can't try to move
  * or drop the AutoreleasePool and screw up the order of the pops.
@@ -37,6 +44,40 @@ macro_rules! autoreleasepool {
     }}
 }
 
+/* Selector references have to live in __objc_selrefs so the runtime can
+ * unique them when the image is loaded, which is what the generated
+ * bindings do for every selector they know about. This lets hand-written
+ * code get the same treatment without depending on generated names.
+ */
+#[macro_export]
+macro_rules! sel {
+    ( $name:expr ) => {{
+        #[link_section="__DATA,__objc_selrefs"]
+        static mut SEL: $crate::objc::SelectorRef =
+            $crate::objc::SelectorRef(concat!($name, "\0").as_ptr());
+        unsafe { SEL }
+    }}
+}
+
+/* Claims an autoreleased return value straight out of the callee's pool.
+ * objc_retainAutoreleasedReturnValue can skip the autorelease and retain
+ * altogether when the caller marks the call site right after the call
+ * returns, which is `mov x29, x29` on arm64 and a `mov %rax, %rdi` into
+ * the retain call on x86_64. There's no way to tie the marker to the call
+ * from Rust the way clang does, so this only emits it as early as it can,
+ * which in practice LLVM keeps next to the call. When it doesn't, the
+ * runtime falls back to a plain retain, which is slower but still right.
+ * This has to be used right after the call returns to have a chance.
+ */
+#[macro_export]
+macro_rules! retain_autoreleased {
+    ( $ret:expr ) => {{
+        #[cfg(target_arch = "aarch64")]
+        ::std::arch::asm!("mov x29, x29", options(nomem, nostack, preserves_flags));
+        $crate::objc::objc_retainAutoreleasedReturnValue($ret)
+    }}
+}
+
 #[repr(C)]
 pub struct ObjCImageInfo {
     pub version: u32,
@@ -71,6 +112,172 @@ pub struct Class {
     pub bits: ClassDataBits,
 }
 
+impl Class {
+    /// Looks up a class registered with the runtime by name, for classes
+    /// that have no generated binding. Returns `None` if no such class
+    /// is loaded.
+    pub fn lookup(name: &str) -> Option<ClassRef> {
+        let name = CString::new(name).ok()?;
+        let cls = unsafe { objc_getClass(name.as_ptr() as *const u8) };
+        if cls.is_null() {
+            None
+        } else {
+            Some(ClassRef(cls))
+        }
+    }
+}
+
+/// A class being put together at runtime, which becomes usable once it's
+/// registered. Dropping it without registering throws it away.
+pub struct ClassBuilder {
+    cls: *mut Class,
+}
+
+impl ClassBuilder {
+    /// Starts a new subclass of `superclass`. Returns `None` if a class
+    /// with that name already exists.
+    pub fn new(name: &str, superclass: ClassRef) -> Option<ClassBuilder> {
+        let name = CString::new(name).ok()?;
+        let cls = unsafe {
+            objc_allocateClassPair(superclass.0, name.as_ptr() as *const u8, 0)
+        };
+        if cls.is_null() {
+            None
+        } else {
+            Some(ClassBuilder { cls: cls })
+        }
+    }
+
+    /// Adds a method, or fails if the class already has one for `sel`
+    /// (overriding a superclass' is fine). This is unsafe since nothing
+    /// checks that `imp` is an `extern "C"` function taking the receiver
+    /// and selector followed by the arguments `types` describes.
+    pub unsafe fn add_method(&mut self, sel: SelectorRef, imp: *const u8, types: &str) -> bool {
+        let types = CString::new(types).unwrap();
+        class_addMethod(self.cls, sel, imp, types.as_ptr() as *const u8)
+    }
+
+    /// Adds room for a `T` to every instance, which `ivar_offset` finds
+    /// again after registration. `types` is its type encoding.
+    pub fn add_ivar<T>(&mut self, name: &str, types: &str) -> bool {
+        let name = CString::new(name).unwrap();
+        let types = CString::new(types).unwrap();
+        let align = mem::align_of::<T>().trailing_zeros() as u8;
+        unsafe {
+            class_addIvar(self.cls, name.as_ptr() as *const u8,
+                          mem::size_of::<T>(), align,
+                          types.as_ptr() as *const u8)
+        }
+    }
+
+    pub fn register(self) -> ClassRef {
+        let cls = self.cls;
+        mem::forget(self);
+        unsafe { objc_registerClassPair(cls) };
+        ClassRef(cls)
+    }
+}
+
+impl Drop for ClassBuilder {
+    fn drop(&mut self) {
+        unsafe { objc_disposeClassPair(self.cls) }
+    }
+}
+
+/// The byte offset of a registered class' ivar, which the runtime picks
+/// once the superclass' layout is known. `None` if there's no such ivar.
+pub fn ivar_offset(cls: ClassRef, name: &str) -> Option<usize> {
+    let name = CString::new(name).ok()?;
+    unsafe {
+        let ivar = class_getInstanceVariable(cls.0, name.as_ptr() as *const u8);
+        if ivar.is_null() {
+            None
+        } else {
+            Some(ivar_getOffset(ivar) as usize)
+        }
+    }
+}
+
+/* Defines an ObjC subclass whose methods are Rust functions:
+ *
+ * define_class! {
+ *     pub struct Counter: NSObject {
+ *         "increment" ("v@:") => counter_increment,
+ *     }
+ * }
+ *
+ * Each method is an `extern "C"` function taking the receiver and
+ * selector first, with its type encoding in parentheses. The class is
+ * registered the first time its classref is asked for. Rust state can be
+ * attached with set_associated, or kept in ivars by registering the class
+ * by hand with a ClassBuilder.
+ */
+#[macro_export]
+macro_rules! define_class {
+    ( $(#[$attr:meta])* $vis:vis struct $name:ident : $superclass:ty {
+        $( $sel:literal ( $types:literal ) => $imp:path ),* $(,)*
+    } ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        $vis struct $name {
+            superclass: $superclass,
+        }
+
+        impl ::std::ops::Deref for $name {
+            type Target = $superclass;
+            fn deref(&self) -> &$superclass {
+                &self.superclass
+            }
+        }
+
+        impl ::std::convert::AsRef<$crate::objc::Object> for $name {
+            fn as_ref(&self) -> &$crate::objc::Object {
+                unsafe { &*(self as *const Self as *const $crate::objc::Object) }
+            }
+        }
+
+        impl $crate::objc::ObjCClass for $name {
+            const START: usize = <$superclass as $crate::objc::ObjCClass>::SIZE;
+            const SIZE: usize = <$superclass as $crate::objc::ObjCClass>::SIZE;
+            const COMPLETE: bool = <$superclass as $crate::objc::ObjCClass>::COMPLETE;
+            fn classref() -> $crate::objc::ClassRef {
+                static INIT: ::std::sync::Once = ::std::sync::Once::new();
+                static mut CLASS: $crate::objc::ClassRef =
+                    $crate::objc::ClassRef(0 as *const $crate::objc::Class);
+                unsafe {
+                    INIT.call_once(|| {
+                        let name = stringify!($name);
+                        let superclass = <$superclass as $crate::objc::ObjCClass>::classref();
+                        CLASS = match $crate::objc::ClassBuilder::new(name, superclass) {
+                            Some(mut builder) => {
+                                $( builder.add_method($crate::sel!($sel), $imp as *const u8, $types); )*
+                                builder.register()
+                            }
+                            None => $crate::objc::Class::lookup(name).unwrap(),
+                        };
+                    });
+                    CLASS
+                }
+            }
+        }
+
+        impl $name {
+            pub fn new() -> Option<$crate::objc::Arc<$name>> {
+                unsafe {
+                    let obj = $crate::objc::alloc_with_zone(
+                        <$name as $crate::objc::ObjCClass>::classref());
+                    let send:
+                        unsafe extern "C" fn(
+                            *mut $crate::objc::Object,
+                            $crate::objc::SelectorRef) -> *mut $name =
+                        ::std::mem::transmute($crate::objc::objc_msgSend as *const u8);
+                    $crate::objc::Arc::new(send(obj, $crate::sel!("init")))
+                }
+            }
+        }
+    }
+}
+
 #[repr(C)]
 pub struct Protocol {
     pub isa: *const Class,
@@ -85,11 +292,46 @@ pub struct Protocol {
     pub flags: u32,
 }
 
+/* BOOL is a real bool on arm64 and 64-bit iOS, but a signed char on
+ * x86_64 macOS and 32-bit iOS, where anything other than 0 counts as YES.
+ * Only 0 and 1 are valid bools in Rust, so BOOLs coming back from ObjC go
+ * through bool_from_objc. Going the other way, bool converts with From.
+ */
+#[cfg(any(target_arch = "aarch64",
+          all(target_os = "ios", target_pointer_width = "64")))]
+pub type BOOL = bool;
+#[cfg(not(any(target_arch = "aarch64",
+              all(target_os = "ios", target_pointer_width = "64"))))]
+pub type BOOL = i8;
+
+#[cfg(any(target_arch = "aarch64",
+          all(target_os = "ios", target_pointer_width = "64")))]
+#[inline]
+pub fn bool_from_objc(b: BOOL) -> bool {
+    b
+}
+#[cfg(not(any(target_arch = "aarch64",
+              all(target_os = "ios", target_pointer_width = "64"))))]
+#[inline]
+pub fn bool_from_objc(b: BOOL) -> bool {
+    b != 0
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct SelectorRef(pub *const u8);
 unsafe impl Sync for SelectorRef {}
 
+impl SelectorRef {
+    /// Registers (or looks up) a selector by name at runtime, for
+    /// selectors that aren't known until then. Prefer `sel!` otherwise.
+    /// None if the name has a NUL in it.
+    pub fn register(name: &str) -> Option<SelectorRef> {
+        let name = CString::new(name).ok()?;
+        Some(unsafe { sel_registerName(name.as_ptr() as *const u8) })
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct ClassRef(pub *const Class);
@@ -100,18 +342,98 @@ pub struct Object {
     pub isa: *const Class,
 }
 
+/* What objc_msgSendSuper2 takes a pointer to. The lookup starts at the
+ * superclass of `class`, so it's the class doing the calling rather than
+ * its superclass.
+ */
 #[repr(C)]
 pub struct Super {
-    pub receiver: Object,
-    pub superclass: *const Class,
+    pub receiver: *mut Object,
+    pub class: *const Class,
 }
 
+/// Implemented by every bound class. The protocols a class conforms to,
+/// including ones its superclasses adopt and ones other protocols inherit,
+/// are traits it implements too, so protocol methods can be called on
+/// classes that conform:
+///
+/// ```
+/// # extern crate rustkit;
+/// # use rustkit::Foundation::{NSCopyingProto, NSMutableString};
+/// fn copyable<T: NSCopyingProto>() {}
+/// copyable::<NSMutableString>();
+/// ```
+///
+/// and only on those:
+///
+/// ```compile_fail
+/// # extern crate rustkit;
+/// # use rustkit::NSObject;
+/// # use rustkit::Foundation::NSCopyingProto;
+/// fn copyable<T: NSCopyingProto>() {}
+/// copyable::<NSObject>();
+/// ```
 pub trait ObjCClass: Sized {
     const START: usize;
     const SIZE: usize;
+    /// False when the bindings were generated without the class' full
+    /// @interface, in which case SIZE is only a lower bound.
+    const COMPLETE: bool = true;
     fn classref() -> ClassRef;
+
+    /// The class itself as an object, for sending it messages. Classes
+    /// are never deallocated, so it isn't retained or released.
+    fn class_object() -> StaticArc<Object> {
+        StaticArc {
+            ptr: unsafe { NonNull::new_unchecked(Self::classref().0 as *mut Object) },
+        }
+    }
+}
+
+/// Implemented by classes whose instances can be used from any thread,
+/// which lets `Arc`s of them be sent and shared between threads. Unsafe
+/// since nothing checks the class really is thread safe.
+pub unsafe trait ThreadSafe: ObjCClass {}
+
+unsafe impl<T: ThreadSafe> Send for Arc<T> {}
+unsafe impl<T: ThreadSafe> Sync for Arc<T> {}
+
+/// Implemented by classes that can be thrown, i.e. NSException and its
+/// subclasses, so code catching exceptions can hand back the right type.
+pub trait ObjCException: ObjCClass {}
+
+// Built from src/exception.m by build.rs.
+extern "C-unwind" {
+    fn rustkit_try(f: extern "C-unwind" fn(*mut u8), ctx: *mut u8, cls: *const Class) -> *mut Object;
+}
+
+/// Runs `f`, catching an ObjC exception of type `E` (or a subclass) if
+/// one is thrown from under it. Other exceptions and panics carry on as
+/// if this weren't here.
+pub fn catch<E: ObjCException, R, F: FnOnce() -> R>(f: F) -> Result<R, Arc<E>> {
+    struct Context<F, R> {
+        f: Option<F>,
+        ret: Option<R>,
+    }
+    extern "C-unwind" fn call<F: FnOnce() -> R, R>(ctx: *mut u8) {
+        let ctx = unsafe { &mut *(ctx as *mut Context<F, R>) };
+        let f = ctx.f.take().unwrap();
+        ctx.ret = Some(f());
+    }
+    let mut ctx = Context { f: Some(f), ret: None };
+    unsafe {
+        let e = rustkit_try(call::<F, R>,
+                            &mut ctx as *mut Context<F, R> as *mut u8,
+                            E::classref().0);
+        if e.is_null() {
+            Ok(ctx.ret.unwrap())
+        } else {
+            Err(Arc::new_unchecked(e as *mut E))
+        }
+    }
 }
 
+#[repr(transparent)]
 pub struct Arc<T> {
     ptr: NonNull<T>,
 }
@@ -134,6 +456,284 @@ impl<T> Arc<T> {
     }
 }
 
+/* Dynamic dispatch for messages the bindings don't cover. These are unsafe
+ * since nothing checks that the receiver responds to the selector or that
+ * it really takes and returns objects.
+ */
+unsafe fn perform(receiver: *mut Object, sel: SelectorRef) -> Option<Arc<Object>> {
+    let send:
+        unsafe extern "C" fn(
+            *mut Object,
+            SelectorRef) -> *mut Object =
+        mem::transmute(objc_msgSend as *const u8);
+    let ret = send(receiver, sel);
+    retain_autoreleased!(ret);
+    Arc::new(ret)
+}
+
+unsafe fn perform_with(receiver: *mut Object, sel: SelectorRef, arg: &Object) -> Option<Arc<Object>> {
+    let send:
+        unsafe extern "C" fn(
+            *mut Object,
+            SelectorRef,
+            *mut Object) -> *mut Object =
+        mem::transmute(objc_msgSend as *const u8);
+    let ret = send(receiver,
+                   sel,
+                   arg as *const Object as *mut Object);
+    retain_autoreleased!(ret);
+    Arc::new(ret)
+}
+
+impl<T> Arc<T> {
+    pub unsafe fn perform(&self, sel: SelectorRef) -> Option<Arc<Object>> {
+        perform(self.ptr.as_ptr() as *mut Object, sel)
+    }
+
+    pub unsafe fn perform_with(&self, sel: SelectorRef, arg: &Object) -> Option<Arc<Object>> {
+        perform_with(self.ptr.as_ptr() as *mut Object, sel, arg)
+    }
+}
+
+/// A reference to an object that's never deallocated, like a class object
+/// or a singleton, which derefs like an `Arc` without ever retaining or
+/// releasing it. The bindings hand back a real `Arc` for everything they
+/// return, since nothing in the headers says an object lives forever, so
+/// this is for code that knows better, like `ObjCClass::class_object`.
+#[repr(transparent)]
+pub struct StaticArc<T> {
+    ptr: NonNull<T>,
+}
+
+unsafe impl<T: ThreadSafe> Send for StaticArc<T> {}
+unsafe impl<T: ThreadSafe> Sync for StaticArc<T> {}
+
+impl<T> StaticArc<T> {
+    /// Unsafe since nothing checks the object really outlives every
+    /// reference to it.
+    pub unsafe fn new(p: *mut T) -> Option<StaticArc<T>> {
+        NonNull::new(p).map(|ptr| StaticArc { ptr: ptr })
+    }
+
+    pub unsafe fn perform(&self, sel: SelectorRef) -> Option<Arc<Object>> {
+        perform(self.ptr.as_ptr() as *mut Object, sel)
+    }
+
+    pub unsafe fn perform_with(&self, sel: SelectorRef, arg: &Object) -> Option<Arc<Object>> {
+        perform_with(self.ptr.as_ptr() as *mut Object, sel, arg)
+    }
+}
+
+impl<T> Clone for StaticArc<T> {
+    fn clone(&self) -> StaticArc<T> {
+        StaticArc { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for StaticArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+/// An object fresh from `alloc()`, before any init... method has run.
+/// All that can be done with one is handing it to an init... method,
+/// which consumes it. Dropping it releases the object uninitialized,
+/// which is what a failing init... method does too.
+pub struct Allocated<T> {
+    ptr: NonNull<T>,
+}
+
+impl<T: ObjCClass> Allocated<T> {
+    pub fn new() -> Allocated<T> {
+        unsafe {
+            let obj = alloc_with_zone(T::classref()) as *mut T;
+            Allocated { ptr: NonNull::new(obj).expect("alloc failed") }
+        }
+    }
+}
+
+impl<T> Allocated<T> {
+    /// Gives up the object and its reference, for an init... method to
+    /// consume.
+    pub fn into_raw(self) -> *mut T {
+        let obj = self.ptr.as_ptr();
+        mem::forget(self);
+        obj
+    }
+}
+
+impl<T> Drop for Allocated<T> {
+    fn drop(&mut self) {
+        unsafe { objc_release(self.ptr.as_ptr() as *mut Object) }
+    }
+}
+
+/* Rust state hung off an ObjC object with an associated object. What gets
+ * associated is a RustKitDropBox holding the boxed state, and its -dealloc
+ * drops the state before calling [super dealloc], so the state goes away
+ * along with the object it's attached to.
+ */
+const OBJC_ASSOCIATION_RETAIN_NONATOMIC: usize = 1;
+
+struct DropSlot {
+    data: *mut u8,
+    drop: unsafe fn(*mut u8),
+}
+
+unsafe fn drop_boxed<S>(data: *mut u8) {
+    drop(Box::from_raw(data as *mut S));
+}
+
+extern "C" fn drop_box_dealloc(this: *mut Object, sel: SelectorRef) {
+    unsafe {
+        let slot = object_getIndexedIvars(this) as *mut DropSlot;
+        ((*slot).drop)((*slot).data);
+        let dealloc: extern "C" fn(*mut Object, SelectorRef) =
+            mem::transmute(class_getMethodImplementation(
+                objc_getClass(b"NSObject\0".as_ptr()), sel));
+        dealloc(this, sel);
+    }
+}
+
+fn drop_box_class() -> *const Class {
+    static INIT: Once = Once::new();
+    static mut CLASS: ClassRef = ClassRef(ptr::null());
+    unsafe {
+        INIT.call_once(|| {
+            let superclass = ClassRef(objc_getClass(b"NSObject\0".as_ptr()));
+            CLASS = match ClassBuilder::new("RustKitDropBox", superclass) {
+                Some(mut builder) => {
+                    builder.add_method(sel!("dealloc"),
+                                       drop_box_dealloc as *const u8,
+                                       "v@:");
+                    builder.register()
+                }
+                // Another copy of RustKit in the process got here first.
+                None => Class::lookup("RustKitDropBox").unwrap(),
+            };
+        });
+        CLASS.0
+    }
+}
+
+impl<T> Arc<T> {
+    /// Attaches `state` to this object under `key`, dropping whatever was
+    /// attached under it before. The state is dropped when the object
+    /// deallocs, which can happen on any thread. Keys are compared by
+    /// address, so use a `static` of your own for each one.
+    pub fn set_associated<S: Send + 'static>(&self, key: &'static u8, state: S) {
+        unsafe {
+            let holder = class_createInstance(drop_box_class(),
+                                              mem::size_of::<DropSlot>());
+            ptr::write(object_getIndexedIvars(holder) as *mut DropSlot,
+                       DropSlot {
+                           data: Box::into_raw(Box::new(state)) as *mut u8,
+                           drop: drop_boxed::<S>,
+                       });
+            objc_setAssociatedObject(self.ptr.as_ptr() as *mut Object,
+                                     key,
+                                     holder,
+                                     OBJC_ASSOCIATION_RETAIN_NONATOMIC);
+            objc_release(holder);
+        }
+    }
+
+    /// Returns the state attached under `key`, if any. It can be shared
+    /// with any thread holding the object, so it has to be `Sync` too.
+    ///
+    /// # Safety
+    ///
+    /// Nothing checks the state was attached as an `S`. The reference
+    /// only lives as long as the state does, and a later `set_associated`
+    /// with the same key (on any thread) drops it, so it mustn't be held
+    /// across one.
+    pub unsafe fn associated<S: Send + Sync + 'static>(&self, key: &'static u8) -> Option<&S> {
+        let holder = objc_getAssociatedObject(
+            self.ptr.as_ptr() as *mut Object, key);
+        if holder.is_null() {
+            return None;
+        }
+        let slot = object_getIndexedIvars(holder) as *const DropSlot;
+        Some(&*((*slot).data as *const S))
+    }
+}
+
+#[repr(C)]
+struct FastEnumerationState {
+    state: usize,
+    items_ptr: *const *mut Object,
+    mutations_ptr: *const usize,
+    extra: [usize; 5],
+}
+
+/// Iterates over a collection conforming to NSFastEnumeration, fetching
+/// objects in batches with -countByEnumeratingWithState:objects:count:
+/// instead of sending a message per object. Like `for ... in` in ObjC,
+/// it panics if the collection is mutated while being enumerated.
+pub struct FastEnumerator<'a> {
+    collection: &'a Object,
+    state: FastEnumerationState,
+    // Boxed since the collection may hand back a pointer into it, which
+    // has to stay put if the enumerator is moved between batches.
+    buf: Box<[*mut Object; 16]>,
+    batch_len: usize,
+    index: usize,
+    mutations: Option<usize>,
+}
+
+impl<'a> FastEnumerator<'a> {
+    /// Unsafe since nothing checks the collection conforms to
+    /// NSFastEnumeration.
+    pub unsafe fn new(collection: &'a Object) -> FastEnumerator<'a> {
+        FastEnumerator {
+            collection: collection,
+            state: mem::zeroed(),
+            buf: Box::new([ptr::null_mut(); 16]),
+            batch_len: 0,
+            index: 0,
+            mutations: None,
+        }
+    }
+}
+
+impl<'a> Iterator for FastEnumerator<'a> {
+    type Item = &'a Object;
+
+    fn next(&mut self) -> Option<&'a Object> {
+        unsafe {
+            if self.index == self.batch_len {
+                let send:
+                    unsafe extern "C" fn(
+                        *mut Object,
+                        SelectorRef,
+                        *mut FastEnumerationState,
+                        *mut *mut Object,
+                        usize) -> usize =
+                    mem::transmute(objc_msgSend as *const u8);
+                self.batch_len = send(self.collection as *const Object as *mut _,
+                                      sel!("countByEnumeratingWithState:objects:count:"),
+                                      &mut self.state,
+                                      self.buf.as_mut_ptr(),
+                                      self.buf.len());
+                self.index = 0;
+                if self.batch_len == 0 {
+                    return None;
+                }
+            }
+            let mutations = *self.state.mutations_ptr;
+            if *self.mutations.get_or_insert(mutations) != mutations {
+                panic!("Collection was mutated while being enumerated");
+            }
+            let obj = *self.state.items_ptr.offset(self.index as isize);
+            self.index += 1;
+            Some(&*obj)
+        }
+    }
+}
+
 impl<T> Clone for Arc<T> {
     fn clone(&self) -> Arc<T> {
         unsafe {
@@ -157,13 +757,233 @@ impl<T> Deref for Arc<T> {
     }
 }
 
-#[link(name = "objc")]
+impl<T: fmt::Display> fmt::Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A zeroing weak reference, which doesn't keep the object alive and
+/// upgrades to None once it's deallocated. Delegates and other back
+/// references can hold one of these to avoid retain cycles.
+pub struct Weak<T> {
+    // The runtime keeps track of where weak references are stored so it
+    // can zero them, so the slot can't move along with the Weak.
+    slot: Box<UnsafeCell<*mut Object>>,
+    _marker: PhantomData<*const T>,
+}
+
+impl<T> Weak<T> {
+    // objc_loadWeak would autorelease the object, which needs a pool.
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        unsafe { Arc::new(objc_loadWeakRetained(self.slot.get()) as *mut T) }
+    }
+}
+
+impl<'a, T> From<&'a Arc<T>> for Weak<T> {
+    fn from(obj: &'a Arc<T>) -> Weak<T> {
+        let slot = Box::new(UnsafeCell::new(ptr::null_mut()));
+        unsafe { objc_storeWeak(slot.get(), obj.ptr.as_ptr() as *mut Object) };
+        Weak {
+            slot,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    fn clone(&self) -> Weak<T> {
+        let slot = Box::new(UnsafeCell::new(ptr::null_mut()));
+        unsafe { objc_copyWeak(slot.get(), self.slot.get()) };
+        Weak {
+            slot,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for Weak<T> {
+    fn drop(&mut self) {
+        unsafe { objc_destroyWeak(self.slot.get()) }
+    }
+}
+
+/* Blocks made from Rust closures. The literal is laid out on the stack the
+ * way clang would lay out a capturing block, then _Block_copy moves it to
+ * the heap, closure and all. The heap copy's dispose helper drops the
+ * closure once the last reference to the block is released.
+ */
+const BLOCK_HAS_COPY_DISPOSE: i32 = 1 << 25;
+
+/// The header every block literal starts with. Block pointers in the
+/// bindings are raw pointers to one of these.
+#[repr(C)]
+pub struct Block {
+    isa: *const u8,
+    flags: i32,
+    reserved: i32,
+    invoke: *const u8,
+}
+
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: usize,
+    size: usize,
+    copy_helper: unsafe extern "C" fn(*mut u8, *const u8),
+    dispose_helper: unsafe extern "C" fn(*mut u8),
+}
+
+#[repr(C)]
+struct ClosureBlock<F> {
+    header: Block,
+    descriptor: *mut BlockDescriptor,
+    closure: F,
+}
+
+unsafe extern "C" fn closure_block_copy(_dst: *mut u8, _src: *const u8) {
+    // _Block_copy already moved the closure over with a memmove, and the
+    // stack literal is forgotten afterwards, so there's nothing to do.
+}
+
+unsafe extern "C" fn closure_block_dispose<F>(block: *mut u8) {
+    let block = block as *mut ClosureBlock<F>;
+    ptr::drop_in_place(&mut (*block).closure);
+    drop(Box::from_raw((*block).descriptor));
+}
+
+/// Closures that can be called through a block taking the arguments in
+/// the tuple `A` and returning `R`.
+pub trait BlockFn<A, R> {
+    #[doc(hidden)]
+    fn invoke_fn() -> *const u8;
+}
+
+macro_rules! block_fn {
+    ( $( $a:ident : $t:ident ),* ) => {
+        impl<$($t,)* R, F: Fn($($t),*) -> R> BlockFn<($($t,)*), R> for F {
+            fn invoke_fn() -> *const u8 {
+                unsafe extern "C" fn invoke<$($t,)* R, F: Fn($($t),*) -> R>(
+                    block: *mut ClosureBlock<F>, $($a: $t),*) -> R {
+                    ((*block).closure)($($a),*)
+                }
+                invoke::<$($t,)* R, F> as *const u8
+            }
+        }
+    }
+}
+
+block_fn!();
+block_fn!(a: A);
+block_fn!(a: A, b: B);
+block_fn!(a: A, b: B, c: C);
+block_fn!(a: A, b: B, c: C, d: D);
+block_fn!(a: A, b: B, c: C, d: D, e: E);
+block_fn!(a: A, b: B, c: C, d: D, e: E, f: G);
+
+/// A reference counted heap block wrapping a Rust closure, taking the
+/// arguments in the tuple `A` and returning `R`. Whoever the block is
+/// handed to may keep it and call it later, from any thread, hence the
+/// bounds on the closure.
+pub struct RcBlock<A, R> {
+    ptr: *mut Block,
+    _marker: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> RcBlock<A, R> {
+    pub fn new<F: BlockFn<A, R> + Send + Sync + 'static>(f: F) -> RcBlock<A, R> {
+        unsafe {
+            let descriptor = Box::into_raw(Box::new(BlockDescriptor {
+                reserved: 0,
+                size: mem::size_of::<ClosureBlock<F>>(),
+                copy_helper: closure_block_copy,
+                dispose_helper: closure_block_dispose::<F>,
+            }));
+            let literal = ClosureBlock {
+                header: Block {
+                    isa: &_NSConcreteStackBlock as *const u8,
+                    flags: BLOCK_HAS_COPY_DISPOSE,
+                    reserved: 0,
+                    invoke: F::invoke_fn(),
+                },
+                descriptor,
+                closure: f,
+            };
+            let ptr = _Block_copy(&literal as *const ClosureBlock<F> as *const Block);
+            mem::forget(literal);
+            RcBlock {
+                ptr,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut Block {
+        self.ptr
+    }
+}
+
+impl<A, R> Clone for RcBlock<A, R> {
+    fn clone(&self) -> RcBlock<A, R> {
+        RcBlock {
+            ptr: unsafe { _Block_copy(self.ptr) },
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<A, R> Drop for RcBlock<A, R> {
+    fn drop(&mut self) {
+        unsafe { _Block_release(self.ptr) }
+    }
+}
+
+// Part of libSystem, so nothing needs linking.
+extern "C" {
+    static _NSConcreteStackBlock: u8;
+    fn _Block_copy(block: *const Block) -> *mut Block;
+    fn _Block_release(block: *const Block);
+    fn dlopen(path: *const u8, mode: i32) -> *mut u8;
+    fn dlclose(handle: *mut u8) -> i32;
+}
+
+const RTLD_NOLOAD: i32 = 0x10;
+
+/// Whether a system framework, e.g. `"AVKit"`, is loaded. Weak-linked
+/// frameworks (see `RUSTKIT_WEAK_FRAMEWORKS`) are loaded at launch if the
+/// system has them at all, so this says whether their bindings are safe
+/// to call.
+pub fn framework_loaded(name: &str) -> bool {
+    let path = match CString::new(format!("/System/Library/Frameworks/{0}.framework/{0}", name)) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    unsafe {
+        let handle = dlopen(path.as_ptr() as *const u8, RTLD_NOLOAD);
+        if handle.is_null() {
+            return false;
+        }
+        dlclose(handle);
+        true
+    }
+}
+
+// Linked by build.rs, see RUSTKIT_OBJC_LIB.
 extern "C" {
     pub fn objc_msgSend(o: *mut Object, op: SelectorRef, ...) -> *mut Object;
-    pub fn objc_msgSendSuper2(o: Super, op: SelectorRef, ...) -> *mut Object;
+    pub fn objc_msgSendSuper2(o: *const Super, op: SelectorRef, ...) -> *mut Object;
     pub fn objc_msgSend_stret(o: *mut Object, op: SelectorRef, ...);
-    pub fn objc_msgSendSuper2_stret(o: Super, op: SelectorRef, ...);
-    pub fn objc_msgSend_fpret(o: *mut Object, op: SelectorRef, ...) -> f32;
+    pub fn objc_msgSendSuper2_stret(o: *const Super, op: SelectorRef, ...);
+    // Only x86 has these, and they're always transmuted to the method's
+    // real signature first, like the others.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn objc_msgSend_fpret(o: *mut Object, op: SelectorRef, ...);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     pub fn objc_msgSend_fp2ret(o: *mut Object, op: SelectorRef, ...);
 
     pub fn objc_retain(o: *mut Object) -> *mut Object;
@@ -171,5 +991,155 @@ extern "C" {
     // this is some magic.
     pub fn objc_retainAutoreleasedReturnValue(o: *mut Object);
 
+    pub fn objc_storeWeak(location: *mut *mut Object, o: *mut Object) -> *mut Object;
+    pub fn objc_loadWeak(location: *mut *mut Object) -> *mut Object;
+    pub fn objc_loadWeakRetained(location: *mut *mut Object) -> *mut Object;
+    pub fn objc_copyWeak(to: *mut *mut Object, from: *mut *mut Object);
+    pub fn objc_destroyWeak(location: *mut *mut Object);
+
     pub fn objc_allocWithZone(o: ClassRef) -> *mut Object;
+    pub fn objc_getClass(name: *const u8) -> *const Class;
+    pub fn sel_registerName(name: *const u8) -> SelectorRef;
+
+    pub fn objc_allocateClassPair(superclass: *const Class, name: *const u8, extra_bytes: usize) -> *mut Class;
+    pub fn objc_registerClassPair(cls: *mut Class);
+    pub fn objc_disposeClassPair(cls: *mut Class);
+    pub fn class_addIvar(cls: *mut Class, name: *const u8, size: usize, alignment: u8, types: *const u8) -> bool;
+    pub fn class_getInstanceVariable(cls: *const Class, name: *const u8) -> *const u8;
+    pub fn ivar_getOffset(ivar: *const u8) -> isize;
+    pub fn class_addMethod(cls: *mut Class, name: SelectorRef, imp: *const u8, types: *const u8) -> bool;
+    pub fn class_getMethodImplementation(cls: *const Class, name: SelectorRef) -> *const u8;
+    pub fn class_createInstance(cls: *const Class, extra_bytes: usize) -> *mut Object;
+    pub fn object_getIndexedIvars(o: *mut Object) -> *mut u8;
+    pub fn objc_setAssociatedObject(o: *mut Object, key: *const u8, value: *mut Object, policy: usize);
+    pub fn objc_getAssociatedObject(o: *mut Object, key: *const u8) -> *mut Object;
+}
+
+/* Methods returning a struct have to be called through objc_msgSend_stret
+ * when the target returns that struct through a hidden pointer instead of
+ * in registers, since the pointer takes the place of self. Declaring the
+ * send as returning the struct makes the compiler pass that pointer, so
+ * all that's left is picking the entry point. arm64 doesn't have one.
+ */
+#[cfg(target_arch = "aarch64")]
+pub fn struct_msg_send<R>() -> *const u8 {
+    objc_msgSend as *const u8
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+pub fn struct_msg_send<R>() -> *const u8 {
+    if returns_in_memory::<R>() {
+        objc_msgSend_stret as *const u8
+    } else {
+        objc_msgSend as *const u8
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn struct_super_msg_send<R>() -> *const u8 {
+    objc_msgSendSuper2 as *const u8
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+pub fn struct_super_msg_send<R>() -> *const u8 {
+    if returns_in_memory::<R>() {
+        objc_msgSendSuper2_stret as *const u8
+    } else {
+        objc_msgSendSuper2 as *const u8
+    }
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+fn returns_in_memory<R>() -> bool {
+    let size = mem::size_of::<R>();
+    if cfg!(target_arch = "x86_64") {
+        size > 16
+    } else if cfg!(target_arch = "x86") {
+        ![1, 2, 4, 8].contains(&size)
+    } else {
+        size > 4
+    }
+}
+
+/* Floating point returns that come back on the x87 stack need
+ * objc_msgSend_fpret, so messages to nil still leave it balanced. That's
+ * every float on 32-bit x86, but only long double on x86_64, where float
+ * and double come back in SSE registers. Nothing else has an fpret.
+ */
+#[cfg(target_arch = "x86")]
+pub fn float_msg_send<R>() -> *const u8 {
+    objc_msgSend_fpret as *const u8
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn float_msg_send<R>() -> *const u8 {
+    if mem::size_of::<R>() > 8 {
+        objc_msgSend_fpret as *const u8
+    } else {
+        objc_msgSend as *const u8
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn float_msg_send<R>() -> *const u8 {
+    objc_msgSend as *const u8
+}
+
+/* C variadic calls need their argument count fixed at compile time, so
+ * nil-terminated object lists without a ...count: counterpart, like
+ * +dictionaryWithObjectsAndKeys:, go through a call for each length, up
+ * to a limit that's plenty for hand-written lists. The first object is
+ * the method's one fixed argument, and the rest go in the variadic tail,
+ * followed by the nil.
+ */
+const OBJECT_LIST_MAX: usize = 16;
+
+macro_rules! send_object_list {
+    ( $send:expr, $receiver:expr, $sel:expr, $o:expr; $( $len:expr => [$($i:expr),*] ),* ) => {
+        match $o.len() {
+            0 => $send($receiver, $sel, ptr::null_mut()),
+            $( $len => $send($receiver, $sel, $( $o[$i] as *const Object as *mut Object, )* ptr::null_mut::<Object>()), )*
+            len => panic!("{} objects is more than the {} a variadic list can take", len, OBJECT_LIST_MAX),
+        }
+    }
+}
+
+pub unsafe fn send_object_list<O, R>(
+    send: unsafe extern "C" fn(O, SelectorRef, *mut Object, ...) -> R,
+    receiver: O,
+    sel: SelectorRef,
+    objects: &[&Object]) -> R {
+    send_object_list!(send, receiver, sel, objects;
+        1 => [0],
+        2 => [0, 1],
+        3 => [0, 1, 2],
+        4 => [0, 1, 2, 3],
+        5 => [0, 1, 2, 3, 4],
+        6 => [0, 1, 2, 3, 4, 5],
+        7 => [0, 1, 2, 3, 4, 5, 6],
+        8 => [0, 1, 2, 3, 4, 5, 6, 7],
+        9 => [0, 1, 2, 3, 4, 5, 6, 7, 8],
+        10 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        11 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+        12 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        13 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+        14 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13],
+        15 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14],
+        16 => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+}
+
+/* Allocation goes through +allocWithZone: instead of straight to the
+ * runtime so class clusters like NSString and NSArray can hand back
+ * their placeholder objects.
+ */
+pub unsafe fn alloc_with_zone(cls: ClassRef) -> *mut Object {
+    let send:
+        unsafe extern "C" fn(
+            *mut Object,
+            SelectorRef,
+            *mut u8) -> *mut Object =
+        mem::transmute(objc_msgSend as *const u8);
+    send(cls.0 as *const Object as *mut _,
+         sel!("allocWithZone:"),
+         ptr::null_mut())
 }
@@ -0,0 +1,25 @@
+extern crate rustkit;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use rustkit::NSObject;
+
+static KEY: u8 = 0;
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+struct State(u32);
+
+impl Drop for State {
+    fn drop(&mut self) {
+        DROPPED.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn associated_state_dropped_with_object() {
+    let obj = NSObject::new().unwrap();
+    obj.set_associated(&KEY, State(42));
+    assert_eq!(unsafe { obj.associated::<State>(&KEY) }.map(|s| s.0), Some(42));
+    assert_eq!(DROPPED.load(Ordering::SeqCst), false);
+    drop(obj);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), true);
+}
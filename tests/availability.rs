@@ -0,0 +1,35 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestAvailabilityProto;
+
+stand_in!(Availability: RKTestAvailabilityProto);
+
+// Calls through this would be ambiguous if the generated trait still had
+// the method, so this only compiles if it was left out.
+#[cfg(rustkit_deployment_target)]
+trait Fallback {
+    fn try_futureValue(&self) -> Option<isize> {
+        Some(-1)
+    }
+}
+
+#[cfg(rustkit_deployment_target)]
+impl Fallback for Availability {}
+
+#[test]
+fn introduced_before_target() {
+    let obj = unsafe { common::instance::<Availability>() };
+    assert_eq!(obj.try_existingValue(), None);
+}
+
+#[test]
+#[cfg(rustkit_deployment_target)]
+fn introduced_after_target() {
+    let obj = unsafe { common::instance::<Availability>() };
+    assert_eq!(obj.try_futureValue(), Some(-1));
+}
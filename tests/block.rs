@@ -0,0 +1,40 @@
+extern crate rustkit;
+
+use std::sync::{Arc, Barrier};
+use std::sync::atomic::{AtomicBool, Ordering};
+use rustkit::objc::RcBlock;
+use rustkit::Foundation::NSThread;
+
+#[test]
+fn void_block_argument() {
+    let barrier = Arc::new(Barrier::new(2));
+    let b = barrier.clone();
+    let block = RcBlock::new(move || {
+        b.wait();
+    });
+    NSThread::detachNewThreadWithBlock_(&block);
+    barrier.wait();
+}
+
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        DROPPED.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn block_drops_closure() {
+    let guard = Guard;
+    let block: RcBlock<(), ()> = RcBlock::new(move || {
+        let _ = &guard;
+    });
+    let copy = block.clone();
+    drop(block);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), false);
+    drop(copy);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), true);
+}
@@ -0,0 +1,65 @@
+extern crate rustkit;
+
+use rustkit::objc::{bool_from_objc, Arc, BOOL};
+use rustkit::Foundation::{NSNumber, NSString};
+
+#[test]
+fn bool_returning_methods() {
+    let yes: Arc<NSNumber> = true.into();
+    let no: Arc<NSNumber> = false.into();
+    assert_eq!(yes.boolValue(), true);
+    assert_eq!(no.boolValue(), false);
+
+    let a = NSString::from_str("a");
+    assert_eq!(a.isEqualToString_(&NSString::from_str("a")), true);
+    assert_eq!(a.isEqualToString_(&NSString::from_str("b")), false);
+}
+
+// Where BOOL is a signed char, anything other than NO is YES.
+#[test]
+fn bool_conversion() {
+    assert_eq!(bool_from_objc(BOOL::from(true)), true);
+    assert_eq!(bool_from_objc(BOOL::from(false)), false);
+    #[cfg(not(any(target_arch = "aarch64",
+                  all(target_os = "ios", target_pointer_width = "64"))))]
+    assert_eq!(bool_from_objc(2), true);
+}
+
+#[cfg(feature = "test_headers")]
+#[macro_use]
+mod common;
+
+#[cfg(feature = "test_headers")]
+mod bool_out {
+    use common;
+    use rustkit::RKTestBoolOutProto;
+    use rustkit::objc::*;
+
+    stand_in!(BoolOut: RKTestBoolOutProto);
+
+    // Any nonzero BOOL is YES, and one that isn't 1 mustn't end up in a
+    // bool as it is.
+    #[cfg(not(any(target_arch = "aarch64",
+                  all(target_os = "ios", target_pointer_width = "64"))))]
+    const YES: BOOL = 2;
+    #[cfg(any(target_arch = "aarch64",
+              all(target_os = "ios", target_pointer_width = "64")))]
+    const YES: BOOL = true;
+
+    extern "C" fn get_flag(_this: *mut Object, _sel: SelectorRef, flag: *mut BOOL) {
+        unsafe { *flag = YES };
+    }
+
+    #[test]
+    fn bool_out_parameter() {
+        let obj: Arc<BoolOut> = unsafe {
+            common::instance_of(common::register_class("RKTestBoolOutImpl", &[
+                ("getFlag:", get_flag as *const u8, "v@:^c"),
+            ]))
+        };
+        let mut flag = false;
+        obj.getFlag_(Some(&mut flag));
+        assert_eq!(flag, true);
+        obj.getFlag_(None);
+    }
+}
@@ -0,0 +1,15 @@
+extern crate rustkit;
+
+use rustkit::Foundation::{NSException, NSGetUncaughtExceptionHandler, NSSetUncaughtExceptionHandler};
+
+extern "C" fn handler(_exception: *mut NSException) {}
+
+#[test]
+fn nullable_callback() {
+    unsafe {
+        NSSetUncaughtExceptionHandler(Some(handler));
+        assert_eq!(NSGetUncaughtExceptionHandler().is_some(), true);
+        NSSetUncaughtExceptionHandler(None);
+        assert_eq!(NSGetUncaughtExceptionHandler().is_none(), true);
+    }
+}
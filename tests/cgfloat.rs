@@ -0,0 +1,27 @@
+extern crate rustkit;
+
+use std::mem;
+use rustkit::CGFloat;
+use rustkit::objc::Arc;
+use rustkit::Foundation::{NSAffineTransform, NSNumber};
+
+#[test]
+fn cgfloat_is_pointer_sized() {
+    assert_eq!(mem::size_of::<CGFloat>(), mem::size_of::<usize>());
+}
+
+#[test]
+fn cgfloat_arguments() {
+    let t = NSAffineTransform::transform();
+    let x: CGFloat = 3.0;
+    t.translateXBy_yBy_(x, x);
+    let tx: CGFloat = t.transformStruct().tX;
+    assert_eq!(tx, 3.0);
+}
+
+#[test]
+fn nsinteger_is_isize() {
+    let n: Arc<NSNumber> = (-5isize).into();
+    let v: isize = n.integerValue();
+    assert_eq!(v, -5);
+}
@@ -0,0 +1,36 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::{Class, ObjCClass, SelectorRef};
+
+#[test]
+fn class_lookup() {
+    let cls = Class::lookup("NSObject");
+    assert_eq!(cls.is_some(), true);
+
+    let cls = cls.unwrap();
+    assert_eq!(cls.0.is_null(), false);
+    assert_eq!(cls.0, <NSObject as ObjCClass>::classref().0);
+}
+
+#[test]
+fn class_lookup_missing() {
+    assert_eq!(Class::lookup("RKNoSuchClass").is_none(), true);
+}
+
+#[test]
+fn class_object_messages() {
+    let cls = NSObject::class_object();
+    assert_eq!(&*cls as *const _ as *const Class, NSObject::classref().0);
+    let same = unsafe { cls.perform(SelectorRef::register("class").unwrap()) }.unwrap();
+    assert_eq!(&*same as *const _ as *const Class, NSObject::classref().0);
+}
+
+#[test]
+fn class_object_clones() {
+    let cls = NSObject::class_object();
+    for _ in 0..100 {
+        drop(cls.clone());
+    }
+    assert_eq!(&*cls as *const _ as *const Class, NSObject::classref().0);
+}
@@ -0,0 +1,16 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+use rustkit::RKTestExtended;
+use rustkit::objc::*;
+
+#[test]
+fn class_extension_setter() {
+    unsafe {
+        let obj = Arc::new(class_createInstance(RKTestExtended::classref().0, 0) as *mut RKTestExtended).unwrap();
+        assert_eq!(obj.level(), 0);
+        obj.setLevel_(3);
+        assert_eq!(obj.level(), 3);
+    }
+}
@@ -0,0 +1,47 @@
+// Shared by the tests of protocols from the test headers, which have no
+// class behind them. A stand-in type conforms to the protocol, and its
+// instances are NSObjects or instances of a class registered here with
+// whatever methods a test needs.
+#![allow(dead_code)]
+
+use rustkit::objc::*;
+
+// Declares a type standing in for an NSObject conforming to the given
+// protocols. Leave them out to implement the protocols by hand.
+macro_rules! stand_in {
+    ($name:ident $(: $($proto:path),+)?) => {
+        struct $name;
+
+        impl ::rustkit::objc::ObjCClass for $name {
+            const START: usize = 0;
+            const SIZE: usize = 0;
+            fn classref() -> ::rustkit::objc::ClassRef {
+                ::rustkit::objc::Class::lookup("NSObject").unwrap()
+            }
+        }
+
+        $($(impl $proto for $name {})+)?
+    };
+}
+
+/// Registers an NSObject subclass implementing `methods`, each given as
+/// its selector, implementation and type encoding. This is unsafe for the
+/// same reasons `ClassBuilder::add_method` is.
+pub unsafe fn register_class(name: &str, methods: &[(&str, *const u8, &str)]) -> ClassRef {
+    let mut builder = ClassBuilder::new(name, Class::lookup("NSObject").unwrap()).unwrap();
+    for &(sel, imp, types) in methods {
+        assert!(builder.add_method(SelectorRef::register(sel).unwrap(), imp, types));
+    }
+    builder.register()
+}
+
+/// A new instance of `cls`, as the stand-in `T`.
+pub unsafe fn instance_of<T: ObjCClass>(cls: ClassRef) -> Arc<T> {
+    Arc::new(class_createInstance(cls.0, 0) as *mut T).unwrap()
+}
+
+/// A new instance of the class `T` stands in for, which has only the
+/// methods NSObject does.
+pub unsafe fn instance<T: ObjCClass>() -> Arc<T> {
+    instance_of(T::classref())
+}
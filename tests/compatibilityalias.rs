@@ -0,0 +1,26 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::{NSObject, RKTestAliasedObject, RKTestCompatibilityAliasProto};
+use rustkit::objc::*;
+
+stand_in!(Alias: RKTestCompatibilityAliasProto);
+
+extern "C" fn is_aliased(_this: *mut Object, _sel: SelectorRef, object: *mut Object) -> BOOL {
+    BOOL::from(!object.is_null())
+}
+
+#[test]
+fn compatibility_alias_parameter() {
+    let obj: Arc<Alias> = unsafe {
+        common::instance_of(common::register_class("RKTestCompatibilityAliasImpl", &[
+            ("isAliased:", is_aliased as *const u8, "c@:@"),
+        ]))
+    };
+    let aliased: Arc<RKTestAliasedObject> = NSObject::new().unwrap();
+    assert_eq!(obj.try_isAliased_(&*aliased), Some(true));
+}
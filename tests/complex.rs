@@ -0,0 +1,40 @@
+extern crate rustkit;
+
+use std::mem;
+use rustkit::Complex;
+
+#[test]
+fn complex_layout() {
+    assert_eq!(mem::size_of::<Complex<f32>>(), 8);
+    assert_eq!(mem::align_of::<Complex<f32>>(), 4);
+    assert_eq!(mem::size_of::<Complex<f64>>(), 16);
+    assert_eq!(mem::align_of::<Complex<f64>>(), 8);
+}
+
+#[cfg(feature = "test_headers")]
+#[macro_use]
+mod common;
+
+#[cfg(feature = "test_headers")]
+mod complex_args {
+    use common;
+    use rustkit::{Complex, RKTestComplexProto};
+    use rustkit::objc::*;
+
+    stand_in!(Parts: RKTestComplexProto);
+
+    extern "C" fn imaginary_part(_this: *mut Object, _sel: SelectorRef, z: Complex<f64>) -> f64 {
+        z.im
+    }
+
+    #[test]
+    fn complex_double_argument() {
+        let obj: Arc<Parts> = unsafe {
+            common::instance_of(common::register_class("RKTestComplexImpl", &[
+                ("imaginaryPart:", imaginary_part as *const u8, "d@:jd"),
+            ]))
+        };
+        let z = Complex { re: 1.5, im: 2.5 };
+        assert_eq!(obj.try_imaginaryPart_(z), Some(2.5));
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use std::mem;
+use rustkit::NSObject;
+use rustkit::RKTestConsumerProto;
+use rustkit::objc::*;
+
+stand_in!(Consumer: RKTestConsumerProto);
+
+// Releases the reference it was handed, like an NS_CONSUMED argument
+// would be under ARC.
+extern "C" fn take_object(_this: *mut Object, _sel: SelectorRef, obj: *mut Object) {
+    unsafe { objc_release(obj) };
+}
+
+fn retain_count(o: &Object) -> usize {
+    unsafe {
+        let send:
+            unsafe extern "C" fn(
+                *mut Object,
+                SelectorRef) -> usize =
+            mem::transmute(objc_msgSend as *const u8);
+        send(o as *const Object as *mut _,
+             SelectorRef::register("retainCount").unwrap())
+    }
+}
+
+#[test]
+fn consumed_argument() {
+    let consumer: Arc<Consumer> = unsafe {
+        common::instance_of(common::register_class("RKTestConsumerImpl", &[
+            ("takeObject:", take_object as *const u8, "v@:@"),
+        ]))
+    };
+    let obj = NSObject::new().unwrap();
+    let before = retain_count(&obj);
+    consumer.takeObject_(&obj);
+    assert_eq!(retain_count(&obj), before);
+}
@@ -0,0 +1,29 @@
+extern crate rustkit;
+
+use rustkit::NSObjectProto;
+use rustkit::objc::Arc;
+use rustkit::Foundation::{NSMutableString, NSNumber, NSString, NSValue};
+
+fn length(s: &NSMutableString) -> usize {
+    unsafe { (*(s as *const NSMutableString as *const NSString)).length() }
+}
+
+#[test]
+fn nsmutablestring_deep_copy() {
+    let abc: Arc<NSString> = "abc".into();
+    let orig = NSMutableString::newWithCapacity_(0);
+    orig.appendString_(&abc);
+    let copy = orig.deep_copy();
+    copy.appendString_(&abc);
+    assert_eq!(length(&orig), 3);
+    assert_eq!(length(&copy), 6);
+}
+
+// NSValue adopts NSCopying, so that's all a copy of an NSNumber is
+// declared to be.
+#[test]
+fn subclass_deep_copy() {
+    let orig: Arc<NSNumber> = 3isize.into();
+    let copy: Arc<NSValue> = orig.deep_copy();
+    assert!(copy.isEqual_(Some(orig.as_ref())));
+}
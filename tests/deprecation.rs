@@ -0,0 +1,18 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestDeprecationProto;
+
+stand_in!(Deprecation: RKTestDeprecationProto);
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_methods_still_bound() {
+    let obj = unsafe { common::instance::<Deprecation>() };
+    assert_eq!(obj.try_oldValue(), None);
+    assert_eq!(obj.try_currentValue(), None);
+}
@@ -0,0 +1,10 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::Foundation::NSArray;
+
+#[test]
+fn designated_initializers() {
+    assert_eq!(NSObject::DESIGNATED_INITIALIZERS, &["init"]);
+    assert!(NSArray::DESIGNATED_INITIALIZERS.contains(&"initWithObjects:count:"));
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestDuplicateArgsProto;
+use rustkit::objc::*;
+
+stand_in!(DuplicateArgs: RKTestDuplicateArgsProto);
+
+extern "C" fn subtract(_this: *mut Object, _sel: SelectorRef, value: isize, from: isize) -> isize {
+    from - value
+}
+
+// Only compiles if the second argument got a name of its own, and checks
+// they're passed in order.
+#[test]
+fn duplicate_argument_names() {
+    let obj: Arc<DuplicateArgs> = unsafe {
+        common::instance_of(common::register_class("RKTestDuplicateArgsImpl", &[
+            ("subtract:from:", subtract as *const u8, "q@:qq"),
+        ]))
+    };
+    assert_eq!(obj.try_subtract_from_(2, 5), Some(3));
+}
@@ -0,0 +1,16 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+use rustkit::{RKTestClosedEnum, RKTestOpenEnum};
+
+#[test]
+fn closed_enum_is_rust_enum() {
+    assert_eq!(RKTestClosedEnum::RKTestClosedSecond as isize, 1);
+}
+
+#[test]
+fn open_enum_is_newtype() {
+    assert_eq!(RKTestOpenEnum::RKTestOpenSecond.0, 1);
+    assert_eq!(RKTestOpenEnum(2) == RKTestOpenEnum::RKTestOpenSecond, false);
+}
@@ -0,0 +1,28 @@
+extern crate rustkit;
+
+use std::collections::HashMap;
+use rustkit::NSObject;
+use rustkit::objc::Arc;
+use rustkit::Foundation::NSString;
+
+#[test]
+fn isequal_equality() {
+    let a: Arc<NSString> = "abc".into();
+    let b: Arc<NSString> = "abc".into();
+    let c: Arc<NSString> = "abd".into();
+    assert_eq!(a == b, true);
+    assert_eq!(a == c, false);
+
+    let o1 = NSObject::new().unwrap();
+    let o2 = NSObject::new().unwrap();
+    assert_eq!(o1 == o1.clone(), true);
+    assert_eq!(o1 == o2, false);
+}
+
+#[test]
+fn hash_map_keys() {
+    let mut map = HashMap::new();
+    map.insert(NSString::from_str("key"), 1);
+    assert_eq!(map.get(&NSString::from_str("key")), Some(&1));
+    assert_eq!(map.get(&NSString::from_str("other")), None);
+}
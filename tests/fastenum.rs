@@ -0,0 +1,22 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::FastEnumerator;
+use rustkit::Foundation::NSArray;
+
+#[test]
+fn nsarray_fast_enumeration() {
+    let objs: Vec<_> = (0..40).map(|_| NSObject::new().unwrap()).collect();
+    let arr = NSArray::from_slice(&objs);
+    let found: Vec<_> = unsafe { FastEnumerator::new(arr.as_ref()) }.collect();
+    assert_eq!(found.len(), 40);
+    for (o, f) in objs.iter().zip(found) {
+        assert_eq!(o.as_ref() as *const _, f as *const _);
+    }
+}
+
+#[test]
+fn empty_fast_enumeration() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    assert_eq!(unsafe { FastEnumerator::new(arr.as_ref()) }.count(), 0);
+}
@@ -0,0 +1,14 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSEnumerationOptions;
+
+// NSEnumerationOptions is declared with shifted values, which clang
+// evaluates for us.
+#[test]
+fn flagenum_shifted_values() {
+    assert_eq!(NSEnumerationOptions::NSEnumerationConcurrent.bits(), 1 << 0);
+    assert_eq!(NSEnumerationOptions::NSEnumerationReverse.bits(), 1 << 1);
+    let both = NSEnumerationOptions::NSEnumerationConcurrent |
+               NSEnumerationOptions::NSEnumerationReverse;
+    assert_eq!(both.bits(), 3);
+}
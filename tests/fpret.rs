@@ -0,0 +1,18 @@
+extern crate rustkit;
+
+use rustkit::objc::Arc;
+use rustkit::Foundation::NSNumber;
+
+#[test]
+fn double_return_keeps_precision() {
+    let n: Arc<NSNumber> = 0.1f64.into();
+    assert_eq!(n.doubleValue(), 0.1f64);
+    let n: Arc<NSNumber> = 1e300f64.into();
+    assert_eq!(n.doubleValue(), 1e300f64);
+}
+
+#[test]
+fn float_return() {
+    let n: Arc<NSNumber> = 0.5f32.into();
+    assert_eq!(n.floatValue(), 0.5f32);
+}
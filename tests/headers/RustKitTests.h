@@ -0,0 +1,154 @@
+// Declarations for the integration tests to bind, for things the SDK
+// doesn't have a convenient example of. Bound into the crate root when
+// the test_headers feature is on. Most things with methods are protocols,
+// which the tests implement at runtime. The few classes are implemented
+// in RustKitTests.m, which build.rs links in alongside.
+
+#import <Foundation/Foundation.h>
+
+@protocol RKTestConsumer
+@optional
+- (void)takeObject:(id) NS_CONSUMED obj;
+@end
+
+@protocol RKTestRequirements
+- (NSInteger)requiredValue;
+@optional
+- (NSInteger)optionalValue;
+@end
+
+@protocol RKTestBase
+@optional
+- (NSInteger)baseValue;
+@end
+
+@protocol RKTestDerived <RKTestBase>
+@optional
+- (NSInteger)derivedValue;
+@end
+
+// Inherit from each other, which only RKTestCycleA's trait keeps as a
+// supertrait.
+@protocol RKTestCycleB;
+
+@protocol RKTestCycleA <RKTestCycleB>
+@optional
+- (NSInteger)cycleAValue;
+@end
+
+@protocol RKTestCycleB <RKTestCycleA>
+@optional
+- (NSInteger)cycleBValue;
+@end
+
+// Both arguments are called value, like the names of
+// -[NSFontCollection renameFontCollectionWithName:visibility:toName:error:].
+@protocol RKTestDuplicateArgs
+@optional
+- (NSInteger)subtract:(NSInteger)value from:(NSInteger)value;
+@end
+
+// The nullability comes from the typedefs, not the methods.
+typedef NSString * _Nullable RKTestMaybeString;
+typedef NSString * _Nonnull RKTestSureString;
+
+@protocol RKTestTypedefNullability
+@optional
+- (RKTestMaybeString)echoMaybe:(RKTestMaybeString)string;
+- (RKTestSureString)echoSure:(RKTestSureString)string;
+@end
+
+@protocol RKTestComplex
+@optional
+- (double)imaginaryPart:(double _Complex)z;
+@end
+
+#define RK_TEST_SELF instancetype
+
+@protocol RKTestWrappedInstancetype
+@optional
+- (RK_TEST_SELF _Nonnull)me;
+@end
+
+@compatibility_alias RKTestAliasedObject NSObject;
+
+@protocol RKTestCompatibilityAlias
+@optional
+- (BOOL)isAliased:(RKTestAliasedObject * _Nonnull)object;
+@end
+
+// The class extension makes the property readwrite, so it gets a setter.
+@interface RKTestExtended : NSObject
+@property (readonly) NSInteger level;
+@end
+
+@interface RKTestExtended ()
+@property (readwrite) NSInteger level;
+@end
+
+@protocol RKTestPointerOut
+@optional
+- (void)getName:(const char * _Nullable * _Nonnull)name;
+@end
+
+@protocol RKTestDeprecation
+@optional
+- (NSInteger)oldValue __attribute__((deprecated("Use currentValue instead")));
+- (NSInteger)currentValue;
+@end
+
+@protocol RKTestAvailability
+@optional
+- (NSInteger)existingValue __attribute__((availability(macos,introduced=10.0))) __attribute__((availability(ios,introduced=2.0)));
+- (NSInteger)futureValue __attribute__((availability(macos,introduced=99.0))) __attribute__((availability(ios,introduced=99.0)));
+@end
+
+@protocol RKTestNoProto
+@optional
+- (NSInteger)valueFromFunction:(NSInteger (*)())function;
+@end
+
+// Not bound on x86_64, where long double goes on the x87 stack.
+@protocol RKTestLongDouble
+@optional
+- (long double)longDoubleValue;
+@end
+
+@protocol RKTestBoolOut
+@optional
+- (void)getFlag:(BOOL *)flag;
+@end
+
+// Fails without setting the error.
+@protocol RKTestSilentFailure
+@optional
+- (BOOL)failWithError:(NSError **)error;
+@end
+
+typedef enum __attribute__((enum_extensibility(closed))) RKTestClosedEnum : NSInteger {
+    RKTestClosedFirst,
+    RKTestClosedSecond,
+} RKTestClosedEnum;
+
+typedef enum __attribute__((enum_extensibility(open))) RKTestOpenEnum : NSInteger {
+    RKTestOpenFirst,
+    RKTestOpenSecond,
+} RKTestOpenEnum;
+
+typedef __attribute__((ext_vector_type(4))) float RKTestFloat4;
+
+// Not bound, since the vector would have to be passed in a SIMD register.
+@protocol RKTestVectors
+- (RKTestFloat4)scaleVector:(RKTestFloat4)v by:(float)factor;
+@end
+
+// The vector field's alignment can't be matched, so this is opaque and
+// nothing passing it by value is bound.
+typedef struct RKTestVertex {
+    RKTestFloat4 position;
+} RKTestVertex;
+
+@protocol RKTestVertices
+@optional
+- (RKTestVertex)origin;
+@end
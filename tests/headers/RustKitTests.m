@@ -0,0 +1,7 @@
+// Implementations of the classes in RustKitTests.h, for the integration
+// tests to link against.
+
+#import "RustKitTests.h"
+
+@implementation RKTestExtended
+@end
@@ -0,0 +1,21 @@
+extern crate rustkit;
+
+use rustkit::Foundation::{NSMutableString, NSString};
+
+#[test]
+fn checked_init() {
+    let s = NSMutableString::try_newWithCapacity_(0);
+    assert_eq!(s.is_some(), true);
+}
+
+#[test]
+fn alloc_then_init() {
+    let s = NSMutableString::initWithCapacity_(NSMutableString::alloc(), 4);
+    s.appendString_(&NSString::from_str("abc"));
+    assert_eq!(s.length(), 3);
+}
+
+#[test]
+fn drop_allocated() {
+    let _ = NSMutableString::alloc();
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestWrappedInstancetypeProto;
+use rustkit::objc::*;
+
+stand_in!(Me: RKTestWrappedInstancetypeProto);
+
+extern "C" fn me(this: *mut Object, _sel: SelectorRef) -> *mut Object {
+    this
+}
+
+// The return type is spelled out, so this only compiles if instancetype
+// behind a macro still came out as Self.
+#[test]
+fn macro_wrapped_instancetype() {
+    let obj: Arc<Me> = unsafe {
+        common::instance_of(common::register_class("RKTestWrappedInstancetypeImpl", &[
+            ("me", me as *const u8, "@@:"),
+        ]))
+    };
+    let same: Option<Arc<Me>> = obj.try_me();
+    assert_eq!(&*same.unwrap() as *const Me, &*obj as *const Me);
+}
@@ -0,0 +1,11 @@
+extern crate rustkit;
+
+use std::mem;
+use rustkit::NSObject;
+use rustkit::objc::ObjCClass;
+
+#[test]
+fn isa_is_first() {
+    assert_eq!(NSObject::IVAR_isa, 0);
+    assert!(NSObject::IVAR_isa + mem::size_of::<usize>() <= <NSObject as ObjCClass>::SIZE);
+}
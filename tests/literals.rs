@@ -0,0 +1,44 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::Arc;
+use rustkit::Foundation::{NSArray, NSDictionary, NSNumber, NSRange, NSString, NSValue};
+
+#[test]
+fn nsstring_from_str() {
+    let s: Arc<NSString> = "hi".into();
+    assert_eq!(s.length(), 2);
+    assert_eq!(s.characterAtIndex_(0), 'h' as u16);
+    assert_eq!(s.characterAtIndex_(1), 'i' as u16);
+}
+
+#[test]
+fn nsnumber_from_numbers() {
+    let n: Arc<NSNumber> = 3.5f64.into();
+    assert_eq!(n.doubleValue(), 3.5);
+    let n: Arc<NSNumber> = (-7i64).into();
+    assert_eq!(n.longLongValue(), -7);
+    let n: Arc<NSNumber> = true.into();
+    assert_eq!(n.boolValue(), true);
+}
+
+#[test]
+fn nsvalue_from_range() {
+    let v: Arc<NSValue> = NSRange::from(2..5).into();
+    assert_eq!(v.rangeValue().to_range(), 2..5);
+}
+
+#[test]
+fn nsarray_from_arcs() {
+    let obj = NSObject::new().unwrap();
+    let arr: Arc<NSArray> = (&[obj.clone(), obj][..]).into();
+    assert_eq!(arr.count(), 2);
+}
+
+#[test]
+fn nsdictionary_from_pairs() {
+    let key: Arc<NSString> = "key".into();
+    let val: Arc<NSNumber> = 1i32.into();
+    let dict: Arc<NSDictionary> = (&[(key, val)][..]).into();
+    assert_eq!(dict.count(), 1);
+}
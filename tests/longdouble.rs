@@ -0,0 +1,38 @@
+extern crate rustkit;
+
+use std::mem;
+use rustkit::LongDouble;
+
+#[test]
+fn longdouble_layout() {
+    assert_eq!(mem::size_of::<LongDouble>(), 16);
+    assert_eq!(mem::align_of::<LongDouble>(), 16);
+}
+
+#[cfg(all(feature = "test_headers", target_arch = "x86_64"))]
+#[macro_use]
+mod common;
+
+#[cfg(all(feature = "test_headers", target_arch = "x86_64"))]
+mod not_bound {
+    use common;
+    use rustkit::RKTestLongDoubleProto;
+
+    stand_in!(LongDoubles: RKTestLongDoubleProto);
+
+    // Calls through this would be ambiguous if the generated trait had
+    // the method too, so this only compiles if it was left out.
+    trait Fallback {
+        fn try_longDoubleValue(&self) -> Option<f64> {
+            Some(-1.0)
+        }
+    }
+
+    impl Fallback for LongDoubles {}
+
+    #[test]
+    fn longdouble_methods_not_bound() {
+        let obj = unsafe { common::instance::<LongDoubles>() };
+        assert_eq!(obj.try_longDoubleValue(), Some(-1.0));
+    }
+}
@@ -0,0 +1,20 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestNoProtoProto;
+
+stand_in!(NoProto: RKTestNoProtoProto);
+
+extern "C" fn seven() -> isize {
+    7
+}
+
+#[test]
+fn function_without_prototype() {
+    let obj = unsafe { common::instance::<NoProto>() };
+    assert_eq!(obj.try_valueFromFunction_(Some(seven)), None);
+}
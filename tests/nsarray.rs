@@ -0,0 +1,73 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::{Arc, Object};
+use rustkit::Foundation::NSArray;
+
+#[test]
+fn nsarray_from_slice() {
+    let obj = NSObject::new().unwrap();
+    let arr = NSArray::from_slice(&[obj.clone(), obj]);
+    assert_eq!(arr.count(), 2);
+}
+
+#[test]
+fn nsarray_from_empty_slice() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    assert_eq!(arr.count(), 0);
+}
+
+#[test]
+fn nsarray_iter() {
+    let objs: Vec<_> = (0..3).map(|_| NSObject::new().unwrap()).collect();
+    let arr = NSArray::from_slice(&objs);
+    let iter = arr.iter();
+    assert_eq!(iter.len(), 3);
+    for (o, f) in objs.iter().zip(iter) {
+        assert_eq!(o.as_ref() as *const Object, &*f as *const Object);
+    }
+}
+
+#[test]
+fn nsarray_iter_empty_and_single() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    assert_eq!(arr.iter().next().is_none(), true);
+    assert_eq!(arr.to_vec().len(), 0);
+
+    let obj = NSObject::new().unwrap();
+    let arr = NSArray::from_slice(&[obj.clone()]);
+    let v = arr.to_vec();
+    assert_eq!(v.len(), 1);
+    assert_eq!(&*v[0] as *const Object, obj.as_ref() as *const Object);
+}
+
+#[test]
+fn nsarray_from_iter() {
+    let arr: Arc<NSArray> = (0..4).map(|_| NSObject::new().unwrap()).collect();
+    assert_eq!(arr.count(), 4);
+    let empty: Arc<NSArray> = Vec::<Arc<NSObject>>::new().into_iter().collect();
+    assert_eq!(empty.count(), 0);
+}
+
+#[test]
+fn nsarray_with_objects() {
+    let objs: Vec<_> = (0..3).map(|_| NSObject::new().unwrap()).collect();
+    let arr = NSArray::arrayWithObjects_(&[objs[0].as_ref(), objs[1].as_ref(), objs[2].as_ref()]);
+    assert_eq!(arr.count(), 3);
+    for (o, f) in objs.iter().zip(arr.iter()) {
+        assert_eq!(o.as_ref() as *const Object, &*f as *const Object);
+    }
+    assert_eq!(NSArray::arrayWithObjects_(&[]).count(), 0);
+}
+
+// These go through +arrayWithObjects:count:, so there's no limit on how
+// many there are.
+#[test]
+fn nsarray_with_many_objects() {
+    let objs: Vec<_> = (0..40).map(|_| NSObject::new().unwrap()).collect();
+    let refs: Vec<&Object> = objs.iter().map(|o| {
+        let o: &Object = o.as_ref();
+        o
+    }).collect();
+    assert_eq!(NSArray::arrayWithObjects_(&refs).count(), 40);
+}
@@ -0,0 +1,22 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSData;
+
+#[test]
+fn nsdata_roundtrip() {
+    let data = NSData::from_bytes(b"hi");
+    assert_eq!(data.as_bytes(), b"hi");
+}
+
+#[test]
+fn nsdata_empty() {
+    let data = NSData::from_bytes(&[]);
+    assert_eq!(data.as_bytes(), b"");
+}
+
+#[test]
+fn nsdata_to_vec() {
+    let bytes: Vec<u8> = (0..=255).collect();
+    let data = NSData::from_bytes(&bytes);
+    assert_eq!(data.to_vec(), bytes);
+}
@@ -0,0 +1,40 @@
+extern crate rustkit;
+
+use rustkit::objc::{Arc, Object};
+use rustkit::Foundation::{NSDictionary, NSNumber, NSString};
+
+fn dictionary() -> Arc<NSDictionary> {
+    let pairs: Vec<(Arc<NSString>, Arc<NSNumber>)> = vec![
+        ("one".into(), 1i32.into()),
+        ("two".into(), 2i32.into()),
+    ];
+    (&pairs[..]).into()
+}
+
+fn int_value(o: &Object) -> i32 {
+    unsafe { (*(o as *const Object as *const NSNumber)).intValue() }
+}
+
+#[test]
+fn nsdictionary_get() {
+    let dict = dictionary();
+    assert_eq!(dict.get("one").map(|v| int_value(&v)), Some(1));
+    assert_eq!(dict.get("three").is_none(), true);
+}
+
+#[test]
+fn nsdictionary_iter() {
+    let dict = dictionary();
+    let mut pairs: Vec<(String, i32)> = dict.iter().map(|(k, v)| {
+        let k = unsafe { (*(&*k as *const Object as *const NSString)).to_string() };
+        (k, int_value(&v))
+    }).collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![("one".to_owned(), 1), ("two".to_owned(), 2)]);
+}
+
+#[test]
+fn values_outlive_dictionary() {
+    let value = dictionary().get("two").unwrap();
+    assert_eq!(int_value(&value), 2);
+}
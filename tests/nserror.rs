@@ -0,0 +1,61 @@
+extern crate rustkit;
+
+use std::env;
+use rustkit::Foundation::NSString;
+
+// NSUTF8StringEncoding
+const UTF8: usize = 4;
+
+#[test]
+fn error_result_round_trip() {
+    let path = env::temp_dir().join("rustkit_nserror_test.txt");
+    let path = NSString::from_str(path.to_str().unwrap());
+    let contents = NSString::from_str("hello");
+    assert_eq!(contents.writeToFile_atomically_encoding_error_(&path, true, UTF8).is_ok(), true);
+    let read = NSString::stringWithContentsOfFile_encoding_error_(&path, UTF8).unwrap();
+    assert_eq!(read.to_string(), "hello");
+}
+
+#[test]
+fn error_result_failure() {
+    let path = NSString::from_str("/nonexistent/rustkit/file.txt");
+    let err = NSString::stringWithContentsOfFile_encoding_error_(&path, UTF8).unwrap_err().unwrap();
+    // NSFileReadNoSuchFileError
+    assert_eq!(err.code(), 260);
+}
+
+#[test]
+fn error_raw_variant() {
+    let path = NSString::from_str("/nonexistent/rustkit/file.txt");
+    let mut error = None;
+    let ret = NSString::stringWithContentsOfFile_encoding_error_raw(&path, UTF8, Some(&mut error));
+    assert_eq!(ret.is_none(), true);
+    assert_eq!(error.is_some(), true);
+}
+
+#[cfg(feature = "test_headers")]
+#[macro_use]
+mod common;
+
+#[cfg(feature = "test_headers")]
+mod silent_failure {
+    use common;
+    use rustkit::RKTestSilentFailureProto;
+    use rustkit::objc::*;
+
+    stand_in!(SilentFailure: RKTestSilentFailureProto);
+
+    extern "C" fn fail(_this: *mut Object, _sel: SelectorRef, _error: *mut *mut Object) -> BOOL {
+        BOOL::from(false)
+    }
+
+    #[test]
+    fn failure_without_error() {
+        let obj: Arc<SilentFailure> = unsafe {
+            common::instance_of(common::register_class("RKTestSilentFailureImpl", &[
+                ("failWithError:", fail as *const u8, "c@:^@"),
+            ]))
+        };
+        assert_eq!(obj.try_failWithError_().map(|r| r.unwrap_err().is_none()), Some(true));
+    }
+}
@@ -0,0 +1,19 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::catch;
+use rustkit::Foundation::{NSArray, NSException};
+
+#[test]
+fn catch_nothing_thrown() {
+    assert_eq!(catch::<NSException, _, _>(|| 5).ok(), Some(5));
+}
+
+#[test]
+fn catch_nsexception() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    let caught = catch::<NSException, _, _>(|| {
+        let _ = arr.objectAtIndex_(5);
+    });
+    assert_eq!(caught.is_err(), true);
+}
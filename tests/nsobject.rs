@@ -2,6 +2,7 @@ extern crate rustkit;
 
 use rustkit::NSObject;
 use rustkit::NSObjectProto;
+use rustkit::Foundation::NSArray;
 
 #[test]
 fn nsobject_new() {
@@ -24,3 +25,17 @@ fn nsobject_description() {
                          unwrap()).collect();
     assert_eq!(&ruststr, "NSObject");
 }
+
+#[test]
+fn nsobject_proto_on_subclass() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    assert_eq!(arr.isProxy(), false);
+}
+
+#[test]
+fn nsobject_id_param_as_ref() {
+    let obj = NSObject::new().unwrap();
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    assert_eq!(obj.isEqual_(Some(arr.as_ref())), false);
+    assert_eq!(obj.isEqual_(Some(obj.as_ref())), true);
+}
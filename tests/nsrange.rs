@@ -0,0 +1,17 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSRange;
+
+#[test]
+fn nsrange_from_range() {
+    let r = NSRange::from(2..5);
+    assert_eq!(r.location, 2);
+    assert_eq!(r.length, 3);
+    assert_eq!(r.to_range(), 2..5);
+}
+
+#[test]
+#[should_panic]
+fn nsrange_from_backwards_range() {
+    let _ = NSRange::from(5..2);
+}
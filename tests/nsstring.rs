@@ -0,0 +1,76 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::Arc;
+use rustkit::Foundation::{NSMutableString, NSRange, NSString};
+
+#[test]
+fn nsstring_out_params() {
+    let desc = NSObject::description().unwrap();
+    let mut start = 1;
+    let mut end = 0;
+    let mut contents_end = 0;
+    desc.getLineStart_end_contentsEnd_forRange_(Some(&mut start),
+                                                Some(&mut end),
+                                                Some(&mut contents_end),
+                                                NSRange::from(0..1));
+    assert_eq!(start, 0);
+    assert_eq!(end, 8);
+    assert_eq!(contents_end, 8);
+}
+
+#[test]
+fn nsstring_with_utf8() {
+    let desc = NSObject::description().unwrap();
+    let len = desc.with_utf8(|s| {
+        assert_eq!(s, "NSObject");
+        s.len()
+    });
+    assert_eq!(len, 8);
+}
+
+#[test]
+fn nsstring_with_utf8_embedded_nul() {
+    let s: Arc<NSString> = "a\0b".into();
+    s.with_utf8(|s| assert_eq!(s, "a\0b"));
+}
+
+#[test]
+fn nsstring_with_utf8_non_ascii() {
+    let s: Arc<NSString> = "h\u{e9}".into();
+    s.with_utf8(|s| assert_eq!(s, "h\u{e9}"));
+}
+
+#[test]
+fn nsstring_to_string() {
+    let desc = NSObject::description().unwrap();
+    assert_eq!(desc.to_string(), "NSObject");
+}
+
+#[test]
+fn nsstring_from_str_round_trip() {
+    for s in &["", "abc", "a\0b", "h\u{e9}llo \u{1f600}"] {
+        assert_eq!(NSString::from_str(s).to_string(), *s);
+    }
+}
+
+#[test]
+fn nsstring_display() {
+    let s: Arc<NSString> = "h\u{e9}llo".into();
+    assert_eq!(format!("{}", s), "h\u{e9}llo");
+    assert_eq!(format!("[{:>7}]", s), "[  h\u{e9}llo]");
+}
+
+#[test]
+fn nsstring_debug() {
+    let s: Arc<NSString> = "say \"hi\"\n".into();
+    assert_eq!(format!("{:?}", s), "\"say \\\"hi\\\"\\n\"");
+}
+
+#[test]
+fn nsmutablestring_display() {
+    let abc: Arc<NSString> = "abc".into();
+    let s = NSMutableString::newWithCapacity_(0);
+    s.appendString_(&abc);
+    assert_eq!(format!("{}", s), "abc");
+}
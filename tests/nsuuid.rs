@@ -0,0 +1,13 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSUUID;
+
+#[test]
+fn nsuuid_bytes_round_trip() {
+    let bytes = [0x12u8, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x4d, 0xef,
+                 0x80, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+    let uuid = NSUUID::newWithUUIDBytes_(&bytes);
+    let mut out = [0u8; 16];
+    uuid.getUUIDBytes_(&mut out);
+    assert_eq!(out, bytes);
+}
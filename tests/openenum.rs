@@ -0,0 +1,16 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSQualityOfService;
+
+// NS_ENUMs aren't closed, so values the headers don't list have to be
+// representable.
+#[test]
+fn open_enum_unknown_value() {
+    assert_eq!(NSQualityOfService::NSQualityOfServiceUserInteractive.0, 0x21);
+    let unknown = NSQualityOfService(0x7f);
+    assert_eq!(unknown == NSQualityOfService::NSQualityOfServiceUtility, false);
+    match NSQualityOfService(0x21) {
+        NSQualityOfService::NSQualityOfServiceUserInteractive => (),
+        _ => panic!("constant didn't match"),
+    }
+}
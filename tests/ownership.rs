@@ -0,0 +1,28 @@
+extern crate rustkit;
+
+use std::mem;
+use rustkit::NSMutableCopyingProto;
+use rustkit::objc::{objc_msgSend, Object, SelectorRef};
+use rustkit::Foundation::NSArray;
+use rustkit::NSObject;
+
+fn retain_count(o: &Object) -> usize {
+    unsafe {
+        let send:
+            unsafe extern "C" fn(
+                *mut Object,
+                SelectorRef) -> usize =
+            mem::transmute(objc_msgSend as *const u8);
+        send(o as *const Object as *mut _,
+             SelectorRef::register("retainCount").unwrap())
+    }
+}
+
+// -mutableCopyWithZone: is in the mutableCopy family, so it returns +1
+// without an ns_returns_retained annotation.
+#[test]
+fn mutable_copy_is_retained() {
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    let copy = arr.mutableCopyWithZone_(None).unwrap();
+    assert_eq!(retain_count(&copy), 1);
+}
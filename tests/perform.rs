@@ -0,0 +1,27 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::Foundation::NSArray;
+use rustkit::objc::SelectorRef;
+
+#[test]
+fn perform_registered_selector() {
+    let obj = NSObject::new().unwrap();
+    let sel = SelectorRef::register("description").unwrap();
+    let desc = unsafe { obj.perform(sel) };
+    assert_eq!(desc.is_some(), true);
+}
+
+#[test]
+fn perform_with_object() {
+    let obj = NSObject::new().unwrap();
+    let arr = NSArray::from_slice::<NSObject>(&[]);
+    let sel = SelectorRef::register("arrayByAddingObject:").unwrap();
+    let added = unsafe { arr.perform_with(sel, obj.as_ref()) };
+    assert_eq!(added.is_some(), true);
+}
+
+#[test]
+fn register_nul_selector() {
+    assert_eq!(SelectorRef::register("bad\0selector").is_none(), true);
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use std::ffi::CStr;
+use rustkit::RKTestPointerOutProto;
+use rustkit::objc::*;
+
+stand_in!(PointerOut: RKTestPointerOutProto);
+
+static NAME: &[u8] = b"written\0";
+
+extern "C" fn get_name(_this: *mut Object, _sel: SelectorRef, name: *mut *const i8) {
+    unsafe { *name = NAME.as_ptr() as *const i8 };
+}
+
+#[test]
+fn pointer_out_parameter() {
+    let obj: Arc<PointerOut> = unsafe {
+        common::instance_of(common::register_class("RKTestPointerOutImpl", &[
+            ("getName:", get_name as *const u8, "v@:^*"),
+        ]))
+    };
+    let mut name = None;
+    obj.getName_(&mut name);
+    assert_eq!(unsafe { CStr::from_ptr(name.unwrap()) }.to_bytes(), b"written");
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestRequirementsProto;
+use rustkit::objc::*;
+
+stand_in!(Requirements);
+
+// Only the required method has to be provided. The optional one keeps its
+// default, which messages the object.
+impl RKTestRequirementsProto for Requirements {
+    fn requiredValue(&self) -> isize {
+        7
+    }
+}
+
+extern "C" fn optional_value(_this: *mut Object, _sel: SelectorRef) -> isize {
+    11
+}
+
+#[test]
+fn required_and_optional_methods() {
+    let obj: Arc<Requirements> = unsafe {
+        common::instance_of(common::register_class("RKTestRequirementsImpl", &[
+            ("optionalValue", optional_value as *const u8, "q@:"),
+        ]))
+    };
+    assert_eq!(obj.requiredValue(), 7);
+    assert_eq!(obj.optionalValue(), 11);
+}
+
+#[test]
+fn unimplemented_optional_method() {
+    let obj = unsafe { common::instance::<Requirements>() };
+    assert_eq!(obj.try_optionalValue(), None);
+}
+
+#[test]
+fn implemented_optional_method() {
+    let obj: Arc<Requirements> = unsafe {
+        common::instance_of(common::register_class("RKTestOptionalImpl", &[
+            ("optionalValue", optional_value as *const u8, "q@:"),
+        ]))
+    };
+    assert_eq!(obj.try_optionalValue(), Some(11));
+}
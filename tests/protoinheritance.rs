@@ -0,0 +1,38 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::{RKTestBaseProto, RKTestCycleAProto, RKTestCycleBProto, RKTestDerivedProto};
+
+stand_in!(Derived: RKTestBaseProto, RKTestDerivedProto);
+
+// Only bounded on the derived protocol, so this only compiles if the base
+// protocol is a supertrait.
+fn base_value<T: RKTestDerivedProto>(o: &T) -> Option<isize> {
+    o.try_baseValue()
+}
+
+#[test]
+fn inherited_methods() {
+    let obj = unsafe { common::instance::<Derived>() };
+    assert_eq!(base_value(&*obj), None);
+    assert_eq!(obj.try_derivedValue(), None);
+}
+
+stand_in!(Cycle: RKTestCycleAProto, RKTestCycleBProto);
+
+// Only compiles if the cycle was broken on RKTestCycleB's side, keeping
+// RKTestCycleB as a supertrait of RKTestCycleA.
+fn cycle_b_value<T: RKTestCycleAProto>(o: &T) -> Option<isize> {
+    o.try_cycleBValue()
+}
+
+#[test]
+fn cyclic_protocols() {
+    let obj = unsafe { common::instance::<Cycle>() };
+    assert_eq!(cycle_b_value(&*obj), None);
+    assert_eq!(obj.try_cycleAValue(), None);
+}
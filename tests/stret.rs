@@ -0,0 +1,18 @@
+extern crate rustkit;
+
+use rustkit::Foundation::NSAffineTransform;
+
+// NSAffineTransformStruct is 48 bytes, which x86_64 returns through a
+// hidden pointer.
+#[test]
+fn large_struct_return() {
+    let t = NSAffineTransform::transform();
+    t.translateXBy_yBy_(3.0, 4.0);
+    let s = t.transformStruct();
+    assert_eq!(s.m11, 1.0);
+    assert_eq!(s.m12, 0.0);
+    assert_eq!(s.m21, 0.0);
+    assert_eq!(s.m22, 1.0);
+    assert_eq!(s.tX, 3.0);
+    assert_eq!(s.tY, 4.0);
+}
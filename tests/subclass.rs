@@ -0,0 +1,71 @@
+#[macro_use]
+extern crate rustkit;
+
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rustkit::{NSObject, NSObjectProto};
+use rustkit::objc::{ivar_offset, Class, ClassBuilder, ObjCClass, Object, SelectorRef};
+
+extern "C" fn answer_hash(_this: *mut Object, _sel: SelectorRef) -> usize {
+    42
+}
+
+define_class! {
+    pub struct RKAnswer: NSObject {
+        "hash" ("Q@:") => answer_hash,
+    }
+}
+
+#[test]
+fn override_method() {
+    let obj = RKAnswer::new().unwrap();
+    assert_eq!(obj.hash(), 42);
+}
+
+static INITS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn counted_init(this: *mut RKCountedInit, _sel: SelectorRef) -> *mut Object {
+    INITS.fetch_add(1, Ordering::SeqCst);
+    unsafe {
+        match (*this).super_init(RKCountedInit::classref()) {
+            Some(obj) => {
+                let obj_ptr = &*obj as *const NSObject as *mut Object;
+                mem::forget(obj);
+                obj_ptr
+            }
+            None => ptr::null_mut(),
+        }
+    }
+}
+
+define_class! {
+    pub struct RKCountedInit: NSObject {
+        "init" ("@@:") => counted_init,
+    }
+}
+
+#[test]
+fn override_calls_super() {
+    let obj = RKCountedInit::new().unwrap();
+    assert_eq!(INITS.load(Ordering::SeqCst), 1);
+    assert_eq!(obj.hash(), &*obj as *const RKCountedInit as usize);
+}
+
+#[test]
+fn registered_once() {
+    let cls = RKAnswer::classref();
+    assert_eq!(cls.0, RKAnswer::classref().0);
+    assert_eq!(Class::lookup("RKAnswer").unwrap().0, cls.0);
+}
+
+#[test]
+fn builder_ivars() {
+    let mut builder = ClassBuilder::new("RKWithIvar", NSObject::classref()).unwrap();
+    assert_eq!(builder.add_ivar::<u64>("value", "Q"), true);
+    let cls = builder.register();
+    let offset = ivar_offset(cls, "value").unwrap();
+    assert!(offset >= <NSObject as ObjCClass>::SIZE);
+    assert_eq!(ivar_offset(cls, "missing"), None);
+    assert_eq!(ClassBuilder::new("RKWithIvar", NSObject::classref()).is_none(), true);
+}
@@ -0,0 +1,20 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::Foundation::NSArray;
+use rustkit::objc::{Class, ObjCClass};
+
+extern "C" {
+    fn class_getInstanceSize(cls: *const Class) -> usize;
+}
+
+// The sizes come from the layout clang parsed the headers with, so they
+// only match the runtime's when that was for the target being built, and
+// not the host, e.g. when cross-compiling for arm64_32 watchOS.
+#[test]
+fn class_sizes_match_runtime() {
+    unsafe {
+        assert_eq!(<NSObject as ObjCClass>::SIZE, class_getInstanceSize(NSObject::classref().0));
+        assert_eq!(<NSArray as ObjCClass>::SIZE, class_getInstanceSize(NSArray::classref().0));
+    }
+}
@@ -0,0 +1,22 @@
+extern crate rustkit;
+
+use std::thread;
+use rustkit::objc::Arc;
+use rustkit::Foundation::NSNumber;
+
+#[test]
+fn send_immutable_values() {
+    let n: Arc<NSNumber> = 42i32.into();
+    let t = thread::spawn(move || n.intValue());
+    assert_eq!(t.join().unwrap(), 42);
+}
+
+#[test]
+fn share_immutable_values() {
+    let n: Arc<NSNumber> = 42i32.into();
+    let n = std::sync::Arc::new(n);
+    let n2 = n.clone();
+    let t = thread::spawn(move || n2.intValue());
+    assert_eq!(t.join().unwrap(), 42);
+    assert_eq!(n.intValue(), 42);
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "RK_CoreFoundation")]
+
+extern crate rustkit;
+
+use rustkit::objc::Arc;
+use rustkit::CoreFoundation::{CFRelease, CFStringGetLength, __CFString};
+use rustkit::Foundation::NSString;
+
+#[test]
+fn nsstring_as_cf() {
+    let s: Arc<NSString> = "abc".into();
+    assert_eq!(unsafe { CFStringGetLength(s.as_cf()) }, 3);
+}
+
+#[test]
+fn nsstring_cf_round_trip() {
+    let s: Arc<NSString> = "abc".into();
+    let cf: *const __CFString = s.into();
+    let s = unsafe { NSString::from_cf(cf) }.unwrap();
+    unsafe { CFRelease(cf as *const _) };
+    assert_eq!(s.length(), 3);
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use rustkit::RKTestTypedefNullabilityProto;
+use rustkit::Foundation::NSString;
+use rustkit::objc::*;
+
+stand_in!(Echo: RKTestTypedefNullabilityProto);
+
+extern "C" fn echo(_this: *mut Object, _sel: SelectorRef, string: *mut Object) -> *mut Object {
+    string
+}
+
+// The argument and return types are spelled out, so this only compiles if
+// each typedef's nullability made it into the signatures.
+#[test]
+fn typedef_nullability() {
+    let obj: Arc<Echo> = unsafe {
+        common::instance_of(common::register_class("RKTestTypedefNullabilityImpl", &[
+            ("echoMaybe:", echo as *const u8, "@@:@"),
+            ("echoSure:", echo as *const u8, "@@:@"),
+        ]))
+    };
+    let abc = NSString::from_str("abc");
+
+    let maybe: Option<Option<Arc<NSString>>> = obj.try_echoMaybe_(None::<&NSString>);
+    assert_eq!(maybe.map(|s| s.is_none()), Some(true));
+    let maybe: Option<Option<Arc<NSString>>> = obj.try_echoMaybe_(Some(&*abc));
+    assert_eq!(maybe.unwrap().unwrap().to_string(), "abc");
+
+    let sure: Option<Arc<NSString>> = obj.try_echoSure_(&*abc);
+    assert_eq!(sure.unwrap().to_string(), "abc");
+}
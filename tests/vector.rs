@@ -0,0 +1,38 @@
+#![cfg(feature = "test_headers")]
+
+extern crate rustkit;
+
+#[macro_use]
+mod common;
+
+use std::mem;
+use rustkit::{RKTestFloat4, RKTestVertex, RKTestVerticesProto};
+
+#[test]
+fn vector_typedef_elements() {
+    let v: RKTestFloat4 = [1.0, 2.0, 3.0, 4.0];
+    assert_eq!(v.len(), 4);
+}
+
+#[test]
+fn vector_record_is_opaque() {
+    assert_eq!(mem::size_of::<RKTestVertex>(), 4);
+}
+
+stand_in!(Vertices: RKTestVerticesProto);
+
+// Calls through this would be ambiguous if the generated trait had the
+// method too, so this only compiles if it was left out.
+trait Fallback {
+    fn try_origin(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+impl Fallback for Vertices {}
+
+#[test]
+fn vector_record_methods_not_bound() {
+    let obj = unsafe { common::instance::<Vertices>() };
+    assert_eq!(obj.try_origin(), Some(0));
+}
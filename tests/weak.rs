@@ -0,0 +1,24 @@
+extern crate rustkit;
+
+use rustkit::NSObject;
+use rustkit::objc::Weak;
+
+#[test]
+fn weak_upgrade() {
+    let obj = NSObject::new().unwrap();
+    let weak = Weak::from(&obj);
+    let strong = weak.upgrade();
+    assert_eq!(strong.is_some(), true);
+    drop(strong);
+    assert_eq!(weak.clone().upgrade().is_some(), true);
+}
+
+#[test]
+fn weak_zeroes_after_dealloc() {
+    let obj = NSObject::new().unwrap();
+    let weak = Weak::from(&obj);
+    let copy = weak.clone();
+    drop(obj);
+    assert_eq!(weak.upgrade().is_none(), true);
+    assert_eq!(copy.upgrade().is_none(), true);
+}
@@ -0,0 +1,10 @@
+extern crate rustkit;
+
+use rustkit::objc::framework_loaded;
+
+#[test]
+fn loaded_frameworks() {
+    assert_eq!(framework_loaded("Foundation"), true);
+    assert_eq!(framework_loaded("RustKitNoSuchFramework"), false);
+    assert_eq!(framework_loaded("Bad\0Name"), false);
+}